@@ -0,0 +1,37 @@
+use std::{env, fs, path::PathBuf};
+
+// zkEVM artifacts that the cli embeds - deployed contracts and their factory dependencies.
+const ARTIFACTS: [(&str, &str); 4] = [
+    ("InteropCenter.sol", "InteropCenter"),
+    ("InteropCenter.sol", "InteropAccount"),
+    ("PaymasterToken.sol", "PaymasterToken"),
+    ("CrossPaymaster.sol", "CrossPaymaster"),
+];
+
+// Copies the artifacts from 'forge build --zksync' (../zkout, or ZKOUT_DIR) next to the build output,
+// so that they are embedded with include_str! - and rebuilt whenever the contracts are.
+// Missing artifacts are embedded as empty files, and have to be passed with --artifacts.
+fn main() {
+    println!("cargo:rerun-if-env-changed=ZKOUT_DIR");
+    let zkout = env::var("ZKOUT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("../zkout"));
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("zkout");
+
+    for (source_file, contract) in ARTIFACTS {
+        let file = format!("{}/{}.json", source_file, contract);
+        let source = zkout.join(&file);
+        println!("cargo:rerun-if-changed={}", source.display());
+
+        let content = fs::read_to_string(&source).unwrap_or_else(|_| {
+            println!(
+                "cargo:warning=zkEVM artifact {} not found - run 'forge build --zksync' to embed it",
+                source.display()
+            );
+            String::new()
+        });
+        let destination = out_dir.join(&file);
+        fs::create_dir_all(destination.parent().unwrap()).unwrap();
+        fs::write(destination, content).unwrap();
+    }
+}
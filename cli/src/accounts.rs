@@ -0,0 +1,430 @@
+use crate::{
+    create2::create2_address, endpoints::EndpointError, receipt_cost, revert::RevertReason,
+    to_human_size, InteropCenter, InteropChain,
+};
+use alloy::{
+    network::ReceiptResponse,
+    primitives::{keccak256, Address, Bytes, B256, U256},
+    sol_types::SolValue,
+};
+use alloy_zksync::provider::zksync_provider;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::Mutex;
+
+// Aliased account of a source chain sender, on a (destination) chain.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasedAccount {
+    pub chain_id: u64,
+    pub source_chain: u64,
+    pub sender: Address,
+    pub address: Address,
+    pub deployed: bool,
+}
+
+// Aliased account deployed by the admin wallet.
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub account: AliasedAccount,
+    pub tx_hash: B256,
+    pub cost: U256,
+}
+
+// Same as getAliasedAccount in the InteropCenter: CREATE2 of the InteropAccount (without constructor input),
+// with salt keccak256(abi.encodePacked(sourceChainId, sourceAccount)).
+pub fn aliased_account_address(
+    interop_address: Address,
+    bytecode_hash: B256,
+    source_chain: u64,
+    sender: Address,
+) -> Address {
+    let mut packed = U256::from(source_chain).to_be_bytes::<32>().to_vec();
+    packed.extend_from_slice(sender.as_slice());
+    create2_address(interop_address, keccak256(packed), bytecode_hash, &[])
+}
+
+// Signature of the type C transaction, that the aliased account (and the paymaster) verify -
+// abi.encode(TransactionReservedStuff, proof), see InteropCenter.decodeSignature.
+pub fn authorization_signature(
+    stuff: InteropCenter::TransactionReservedStuff,
+    proof: Bytes,
+) -> Bytes {
+    (stuff, proof).abi_encode_params().into()
+}
+
+// Keeps track of the aliased accounts - their addresses are computed locally, and only the accounts
+// that are not deployed yet are checked on chain.
+pub struct AccountManager {
+    // (chain, source chain, sender) -> account.
+    accounts: Mutex<HashMap<(u64, u64, Address), AliasedAccount>>,
+    // Bytecode hash of the InteropAccount from the artifacts - the same on all the chains.
+    artifacts_bytecode_hash: Option<B256>,
+    // Otherwise it is read from each (chain, interop center) once.
+    bytecode_hashes: Mutex<HashMap<(u64, Address), B256>>,
+}
+
+impl AccountManager {
+    // Starts with the accounts that were known before the restart.
+    pub fn new(known: Vec<AliasedAccount>, artifacts_bytecode_hash: Option<B256>) -> Self {
+        AccountManager {
+            accounts: Mutex::new(
+                known
+                    .into_iter()
+                    .map(|account| {
+                        (
+                            (account.chain_id, account.source_chain, account.sender),
+                            account,
+                        )
+                    })
+                    .collect(),
+            ),
+            artifacts_bytecode_hash,
+            bytecode_hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn known(&self) -> Vec<AliasedAccount> {
+        let mut accounts: Vec<_> = self.accounts.lock().await.values().cloned().collect();
+        accounts.sort_by_key(|account| (account.chain_id, account.source_chain, account.sender));
+        accounts
+    }
+
+    async fn bytecode_hash(&self, chain: &InteropChain) -> Result<B256, EndpointError> {
+        if let Some(hash) = self.artifacts_bytecode_hash {
+            return Ok(hash);
+        }
+        let key = (chain.chain_id, chain.interop_address);
+        if let Some(hash) = self.bytecode_hashes.lock().await.get(&key) {
+            return Ok(*hash);
+        }
+        let hash = chain.get_aliased_account_bytecode_hash().await?;
+        self.bytecode_hashes.lock().await.insert(key, hash);
+        Ok(hash)
+    }
+
+    // Address of the aliased account - without any calls, if the bytecode hash came from the artifacts.
+    pub async fn address(
+        &self,
+        chain: &InteropChain,
+        source_chain: u64,
+        sender: Address,
+    ) -> Result<Address, EndpointError> {
+        Ok(aliased_account_address(
+            chain.interop_address,
+            self.bytecode_hash(chain).await?,
+            source_chain,
+            sender,
+        ))
+    }
+
+    // Compares the derived address with getAliasedAccount of each chain - they differ when the artifacts
+    // don't match the deployed InteropCenter.
+    pub async fn cross_check(
+        &self,
+        chains: &HashMap<u64, Arc<InteropChain>>,
+    ) -> anyhow::Result<()> {
+        for chain in chains.values() {
+            let sender = chain.admin_address;
+            let derived = self.address(chain, chain.chain_id, sender).await?;
+            let on_chain = chain
+                .get_aliased_account_address(U256::from(chain.chain_id), sender)
+                .await?;
+            anyhow::ensure!(
+                derived == on_chain,
+                "Aliased account of {} on chain {} is {}, but it was derived as {} - check the artifacts",
+                sender,
+                chain.chain_id,
+                on_chain,
+                derived
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn get(
+        &self,
+        chain: &InteropChain,
+        source_chain: u64,
+        sender: Address,
+    ) -> Result<AliasedAccount, EndpointError> {
+        let key = (chain.chain_id, source_chain, sender);
+        let known = self.accounts.lock().await.get(&key).cloned();
+        // Known entries are used only while they match the current interop center - after it is
+        // redeployed (e.g. with another salt), the accounts have new addresses.
+        let address = self.address(chain, source_chain, sender).await?;
+        let mut account = match known {
+            // Accounts are never removed.
+            Some(account) if account.address == address && account.deployed => return Ok(account),
+            Some(account) if account.address == address => account,
+            _ => AliasedAccount {
+                chain_id: chain.chain_id,
+                source_chain,
+                sender,
+                address,
+                deployed: false,
+            },
+        };
+        account.deployed = chain.has_code(account.address).await?;
+        self.accounts.lock().await.insert(key, account.clone());
+        Ok(account)
+    }
+
+    // Deploys the account, unless it is deployed already.
+    pub async fn ensure_deployed(
+        &self,
+        chain: &InteropChain,
+        source_chain: u64,
+        sender: Address,
+    ) -> Result<Option<Deployment>, RevertReason> {
+        self.deploy_batch(chain, &[(source_chain, sender)])
+            .await
+            .pop()
+            .unwrap()
+    }
+
+    // Sends the deployments of all the missing accounts first, and only then waits for them.
+    // Returns the result for each of the (source chain, sender) pairs.
+    pub async fn deploy_batch(
+        &self,
+        chain: &InteropChain,
+        senders: &[(u64, Address)],
+    ) -> Vec<Result<Option<Deployment>, RevertReason>> {
+        let admin_provider = zksync_provider()
+            .with_recommended_fillers()
+            .wallet(chain.admin_wallet.clone())
+            .on_http(chain.rpc().parse().unwrap());
+        let contract = InteropCenter::new(chain.interop_address, &admin_provider);
+
+        let mut sent = vec![];
+        for (source_chain, sender) in senders {
+            let account = match self.get(chain, *source_chain, *sender).await {
+                Ok(account) => account,
+                Err(error) => {
+                    sent.push(Err(RevertReason::from(error)));
+                    continue;
+                }
+            };
+            if account.deployed {
+                sent.push(Ok((account, None)));
+                continue;
+            }
+            let pending = contract
+                .deployAliasedAccount(*sender, U256::from(*source_chain))
+                .send()
+                .await
+                .map_err(|error| RevertReason::from_contract_error(&error));
+            sent.push(pending.map(|pending| (account, Some(pending))));
+        }
+
+        let mut results = vec![];
+        for entry in sent {
+            let (mut account, pending) = match entry {
+                Ok((_, None)) => {
+                    results.push(Ok(None));
+                    continue;
+                }
+                Ok((account, Some(pending))) => (account, pending),
+                Err(reason) => {
+                    results.push(Err(reason));
+                    continue;
+                }
+            };
+            let receipt = match pending.get_receipt().await {
+                Ok(receipt) => receipt,
+                Err(error) => {
+                    results.push(Err(RevertReason::Other(error.to_string())));
+                    continue;
+                }
+            };
+            if !receipt.status() {
+                results.push(Err(RevertReason::Other(format!(
+                    "deployment tx {:?} reverted",
+                    receipt.transaction_hash()
+                ))));
+                continue;
+            }
+            println!(
+                "   Deployed aliased account on chain {} {:?} with tx {:?}",
+                chain.chain_id,
+                account.address,
+                receipt.transaction_hash()
+            );
+            account.deployed = true;
+            self.accounts.lock().await.insert(
+                (account.chain_id, account.source_chain, account.sender),
+                account.clone(),
+            );
+            results.push(Ok(Some(Deployment {
+                account,
+                tx_hash: receipt.transaction_hash(),
+                cost: receipt_cost(&receipt),
+            })));
+        }
+        results
+    }
+}
+
+fn print_account(account: &AliasedAccount, balance: Option<U256>) {
+    println!(
+        "  chain {:>6} source {:>6} sender {} -> {} {}{}",
+        account.chain_id,
+        account.source_chain,
+        account.sender,
+        account.address,
+        if account.deployed {
+            "deployed"
+        } else {
+            "not deployed"
+        },
+        balance
+            .map(|balance| format!(" balance {}", to_human_size(balance)))
+            .unwrap_or_default()
+    );
+}
+
+// Prints the known accounts, with their current status on the configured chains.
+pub async fn list(chains: &HashMap<u64, Arc<InteropChain>>, manager: &AccountManager) {
+    println!("Aliased accounts:");
+    for account in manager.known().await {
+        match chains.get(&account.chain_id) {
+            Some(chain) => match manager
+                .get(chain, account.source_chain, account.sender)
+                .await
+            {
+                Ok(account) => {
+                    print_account(&account, chain.get_balance(account.address).await.ok())
+                }
+                Err(error) => {
+                    println!(
+                        "!! Can't check the account on chain {}: {}",
+                        chain.chain_id, error
+                    );
+                    print_account(&account, None);
+                }
+            },
+            // Chain is not configured - status from the state file.
+            None => print_account(&account, None),
+        }
+    }
+}
+
+// Prints the aliased account of the sender on each of the configured chains.
+pub async fn show(
+    chains: &HashMap<u64, Arc<InteropChain>>,
+    manager: &AccountManager,
+    source_chain: u64,
+    sender: Address,
+) {
+    let mut chain_ids: Vec<_> = chains.keys().copied().collect();
+    chain_ids.sort();
+    println!(
+        "Aliased accounts of {} from chain {}:",
+        sender, source_chain
+    );
+    for chain_id in chain_ids {
+        let chain = &chains[&chain_id];
+        match manager.get(chain, source_chain, sender).await {
+            Ok(account) => print_account(&account, chain.get_balance(account.address).await.ok()),
+            Err(error) => println!(
+                "!! Can't check the account on chain {}: {}",
+                chain_id, error
+            ),
+        }
+    }
+}
+
+// Deploys the aliased accounts of the senders - on the given chain, or on all the other configured chains.
+pub async fn deploy(
+    chains: &HashMap<u64, Arc<InteropChain>>,
+    manager: &AccountManager,
+    source_chain: u64,
+    senders: &[Address],
+    chain_id: Option<u64>,
+) -> anyhow::Result<()> {
+    let mut targets: Vec<_> = match chain_id {
+        Some(chain_id) => vec![chains
+            .get(&chain_id)
+            .ok_or_else(|| anyhow::anyhow!("Chain {} is not configured", chain_id))?
+            .clone()],
+        None => chains
+            .values()
+            .filter(|chain| chain.chain_id != source_chain)
+            .cloned()
+            .collect(),
+    };
+    targets.sort_by_key(|chain| chain.chain_id);
+
+    let senders: Vec<_> = senders
+        .iter()
+        .map(|sender| (source_chain, *sender))
+        .collect();
+    let mut failed = 0;
+    for chain in targets {
+        println!("Deploying aliased accounts on chain {}:", chain.chain_id);
+        let results = manager.deploy_batch(&chain, &senders).await;
+        for ((_, sender), result) in senders.iter().zip(results) {
+            match result {
+                Ok(Some(deployment)) => println!(
+                    "  {} -> {} deployed with tx {:?} (cost {})",
+                    sender,
+                    deployment.account.address,
+                    deployment.tx_hash,
+                    to_human_size(deployment.cost)
+                ),
+                Ok(None) => println!("  {} - already deployed", sender),
+                Err(reason) => {
+                    println!("!! {} - deployment failed: {}", sender, reason.describe());
+                    failed += 1;
+                }
+            }
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} deployments failed", failed);
+    Ok(())
+}
+
+// Prints the aliased address of the sender on every configured chain (no calls with the artifacts).
+pub async fn alias(
+    chains: &HashMap<u64, Arc<InteropChain>>,
+    manager: &AccountManager,
+    source_chain: u64,
+    sender: Address,
+) {
+    let mut chain_ids: Vec<_> = chains.keys().copied().collect();
+    chain_ids.sort();
+    for chain_id in chain_ids {
+        match manager
+            .address(&chains[&chain_id], source_chain, sender)
+            .await
+        {
+            Ok(address) => println!("  chain {:>6}: {}", chain_id, address),
+            Err(error) => println!("!! chain {:>6}: {}", chain_id, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, b256};
+
+    // Same vector as AliasedAccountDerivation in test/PaymasterTokenTest.t.sol, which also checks
+    // the formula against getAliasedAccount of a deployed InteropCenter.
+    const INTEROP_ADDRESS: Address = address!("6Fb7817d183F7C84A546770338bf1F5d2111e43a");
+    const SENDER: Address = address!("5f3649BBfCE8f62738c8346588e0F62469087d9e");
+    const BYTECODE_HASH: B256 =
+        b256!("0100000309d6a718960241fb8a688613360d372f228972cd037fbdb39bbf0bde");
+
+    #[test]
+    fn aliased_address() {
+        assert_eq!(
+            aliased_account_address(INTEROP_ADDRESS, BYTECODE_HASH, 500, SENDER),
+            address!("f612B91303ec9f04b1F9c1Db3748f3264Bd935e5")
+        );
+        // Salt includes the source chain.
+        assert_eq!(
+            aliased_account_address(INTEROP_ADDRESS, BYTECODE_HASH, 501, SENDER),
+            address!("2C7f8C52eAE62Fb3a4cddA31CbAC9811270e81ea")
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    accounts::AliasedAccount, rebalance::spawn_rebalance, treasury::TreasuryReport,
+    validate::validate_chain, InteropChain, MessageStatus, RejectedLog, Relayer,
+};
+use alloy::primitives::{Address, FixedBytes, U256};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+};
+
+type AdminResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+#[derive(Deserialize)]
+struct MessagesQuery {
+    // pending, relayed, failed, retrying, underpriced, refused or skipped. All messages if not set.
+    status: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MessageEntry {
+    msg_hash: FixedBytes<32>,
+    chain_id: u64,
+    message_num: U256,
+    type_c: bool,
+    source_log_removed: bool,
+    status: MessageStatus,
+}
+
+#[derive(Deserialize)]
+struct RescanRequest {
+    from_block: u64,
+    to_block: u64,
+}
+
+// Chain that joined the network (also sent by the 'add-chain' command).
+#[derive(Serialize, Deserialize)]
+pub struct AddChainRequest {
+    // Comma separated for multiple endpoints.
+    pub rpc: String,
+    pub interop_address: Address,
+    pub base_token_price: u64,
+}
+
+fn status_name(status: &MessageStatus) -> &'static str {
+    match status {
+        MessageStatus::Pending => "pending",
+        MessageStatus::Relayed => "relayed",
+        MessageStatus::Failed(_) => "failed",
+        MessageStatus::Retrying(_) => "retrying",
+        MessageStatus::Underpriced(_) => "underpriced",
+        MessageStatus::Refused(_) => "refused",
+        MessageStatus::Skipped => "skipped",
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<FixedBytes<32>, (StatusCode, String)> {
+    FixedBytes::from_str(hash).map_err(|error| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid message hash {}: {}", hash, error),
+        )
+    })
+}
+
+fn not_found(what: String) -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, format!("{} not found", what))
+}
+
+async fn list_messages(
+    State(relayer): State<Relayer>,
+    Query(query): Query<MessagesQuery>,
+) -> AdminResult<Vec<MessageEntry>> {
+    let map = relayer.shared_map.lock().await;
+    let mut messages: Vec<_> = map
+        .values()
+        .filter(|msg| match &query.status {
+            Some(status) => status_name(&msg.status) == status.as_str(),
+            None => true,
+        })
+        .map(|msg| MessageEntry {
+            msg_hash: msg.msg_hash,
+            chain_id: msg.chain_id,
+            message_num: msg.interop_message.messageNum,
+            type_c: msg.is_type_c(),
+            source_log_removed: msg.source_log_removed,
+            status: msg.status.clone(),
+        })
+        .collect();
+    messages.sort_by_key(|msg| (msg.chain_id, msg.message_num));
+    Ok(Json(messages))
+}
+
+async fn list_rejected_logs(State(relayer): State<Relayer>) -> AdminResult<Vec<RejectedLog>> {
+    Ok(Json(relayer.rejected_logs.lock().await.clone()))
+}
+
+async fn list_accounts(State(relayer): State<Relayer>) -> AdminResult<Vec<AliasedAccount>> {
+    Ok(Json(relayer.accounts.known().await))
+}
+
+// Processes the message again (in the background) - only the ones that didn't go through.
+async fn retry_message(
+    State(relayer): State<Relayer>,
+    Path(hash): Path<String>,
+) -> AdminResult<MessageStatus> {
+    let msg_hash = parse_hash(&hash)?;
+    let msg = {
+        let map = relayer.shared_map.lock().await;
+        let msg = map
+            .get(&msg_hash)
+            .ok_or_else(|| not_found(format!("Message {}", hash)))?;
+        match msg.status {
+            MessageStatus::Failed(_) | MessageStatus::Retrying(_) | MessageStatus::Refused(_) => {}
+            _ => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "Message {} is {} - only failed, retrying or refused ones are retried",
+                        hash,
+                        status_name(&msg.status)
+                    ),
+                ))
+            }
+        }
+        msg.clone()
+    };
+
+    println!("Admin: retrying msg {:?}", msg_hash);
+    // Retried right away instead - process_message makes sure it doesn't run twice at the same time.
+    relayer.cancel_retry(&msg_hash).await;
+    relayer.spawn_background({
+        let relayer = relayer.clone();
+        async move { relayer.process_message(msg).await }
+    });
+    Ok(Json(MessageStatus::Pending))
+}
+
+async fn skip_message(
+    State(relayer): State<Relayer>,
+    Path(hash): Path<String>,
+) -> AdminResult<MessageStatus> {
+    let msg_hash = parse_hash(&hash)?;
+    relayer.cancel_retry(&msg_hash).await;
+    let mut map = relayer.shared_map.lock().await;
+    let msg = map
+        .get_mut(&msg_hash)
+        .ok_or_else(|| not_found(format!("Message {}", hash)))?;
+
+    println!("Admin: skipping msg {:?}", msg_hash);
+    msg.status = MessageStatus::Skipped;
+    Ok(Json(msg.status.clone()))
+}
+
+// Re-scans the block range (in the background).
+async fn rescan_chain(
+    State(relayer): State<Relayer>,
+    Path(chain_id): Path<u64>,
+    Json(request): Json<RescanRequest>,
+) -> AdminResult<String> {
+    if relayer.chain(chain_id).await.is_none() {
+        return Err(not_found(format!("Chain {}", chain_id)));
+    }
+    if request.from_block > request.to_block {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "from_block must not be larger than to_block".to_string(),
+        ));
+    }
+    println!(
+        "Admin: rescanning chain {} blocks {}..={}",
+        chain_id, request.from_block, request.to_block
+    );
+    relayer.spawn_background({
+        let relayer = relayer.clone();
+        async move {
+            match relayer
+                .rescan(chain_id, request.from_block, request.to_block)
+                .await
+            {
+                Ok(count) => println!("Admin: rescan of chain {} found {} logs", chain_id, count),
+                Err(err) => println!("!! Admin: rescan of chain {} failed: {}", chain_id, err),
+            }
+        }
+    });
+    Ok(Json("started".to_string()))
+}
+
+async fn set_paused(relayer: &Relayer, chain_id: u64, paused: bool) -> AdminResult<bool> {
+    let chain = relayer
+        .chain(chain_id)
+        .await
+        .ok_or_else(|| not_found(format!("Chain {}", chain_id)))?;
+    chain.paused.store(paused, Ordering::Relaxed);
+    Ok(Json(paused))
+}
+
+async fn pause_chain(
+    State(relayer): State<Relayer>,
+    Path(chain_id): Path<u64>,
+) -> AdminResult<bool> {
+    set_paused(&relayer, chain_id, true).await
+}
+
+async fn resume_chain(
+    State(relayer): State<Relayer>,
+    Path(chain_id): Path<u64>,
+) -> AdminResult<bool> {
+    set_paused(&relayer, chain_id, false).await
+}
+
+// Runs the treasury check on all the chains now (instead of waiting for the next interval).
+async fn refill_paymasters(State(relayer): State<Relayer>) -> AdminResult<Vec<TreasuryReport>> {
+    let mut result = vec![];
+    for chain in relayer.chains().await.values() {
+        let report = relayer.treasury.check(chain).await.map_err(|error| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "Treasury check on chain {} failed: {}",
+                    chain.chain_id, error
+                ),
+            )
+        })?;
+        result.push(report);
+    }
+    result.sort_by_key(|report| report.chain_id);
+    Ok(Json(result))
+}
+
+// Starts sweeping the paymaster tokens now (instead of waiting for the next interval) - the results
+// are in the relayer logs.
+async fn rebalance_paymasters(State(relayer): State<Relayer>) -> AdminResult<bool> {
+    if !spawn_rebalance(relayer) {
+        return Err((
+            StatusCode::CONFLICT,
+            "Rebalancing is already running".to_string(),
+        ));
+    }
+    Ok(Json(true))
+}
+
+// Starts relaying the chain - its trust and paymasters should already be set up ('add-chain' command).
+async fn add_chain(
+    State(relayer): State<Relayer>,
+    Json(request): Json<AddChainRequest>,
+) -> AdminResult<u64> {
+    let chain = InteropChain::connect(
+        &request.rpc,
+        request.interop_address,
+        request.base_token_price,
+        relayer.settings.poll_interval,
+        relayer.settings.confirmations,
+        &relayer.settings,
+    )
+    .await
+    .map_err(|error| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to connect to {}: {}", request.rpc, error),
+        )
+    })?;
+    let chain_id = chain.chain_id;
+    let report = validate_chain(&chain).await;
+    if !report.is_ok() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Contract validation failed on chain {}: {}",
+                chain_id,
+                report.errors().join("; ")
+            ),
+        ));
+    }
+    if !relayer.add_chain(Arc::new(chain)).await {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Chain {} is already relayed", chain_id),
+        ));
+    }
+    println!(
+        "Admin: added chain {} - pass it with --rpc to keep it after restart",
+        chain_id
+    );
+    relayer.policy.check(&relayer.chains().await);
+    Ok(Json(chain_id))
+}
+
+async fn remove_chain(
+    State(relayer): State<Relayer>,
+    Path(chain_id): Path<u64>,
+) -> AdminResult<u64> {
+    if !relayer.remove_chain(chain_id).await {
+        return Err(not_found(format!("Chain {}", chain_id)));
+    }
+    println!("Admin: removed chain {}", chain_id);
+    Ok(Json(chain_id))
+}
+
+pub fn admin_routes(relayer: Relayer) -> Router {
+    Router::new()
+        .route("/admin/messages", get(list_messages))
+        .route("/admin/rejected-logs", get(list_rejected_logs))
+        .route("/admin/accounts", get(list_accounts))
+        .route("/admin/messages/:hash/retry", post(retry_message))
+        .route("/admin/messages/:hash/skip", post(skip_message))
+        .route("/admin/chains", post(add_chain))
+        .route("/admin/chains/:chain_id", delete(remove_chain))
+        .route("/admin/chains/:chain_id/rescan", post(rescan_chain))
+        .route("/admin/chains/:chain_id/pause", post(pause_chain))
+        .route("/admin/chains/:chain_id/resume", post(resume_chain))
+        .route("/admin/paymasters/refill", post(refill_paymasters))
+        .route("/admin/paymasters/rebalance", post(rebalance_paymasters))
+        .with_state(relayer)
+}
@@ -0,0 +1,383 @@
+use crate::{
+    paymaster::PaymasterFlow, to_human_size, treasury::DailySpend,
+    InteropCenter::InteropTransaction, InteropChain,
+};
+use alloy::primitives::{Address, FixedBytes, U256};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+// Limits on the type C transactions that the relayer is willing to submit (and pay for).
+#[derive(Debug, Clone)]
+pub struct AdmissionConfig {
+    // Paymasters accepted on top of the preferred paymaster of the destination chain.
+    pub extra_paymasters: HashSet<Address>,
+    pub max_gas_limit: U256,
+    pub max_gas_price: U256,
+    // Type C transactions per source chain sender per minute.
+    pub max_per_sender_per_minute: usize,
+    // How much the relayer sends to the aliased account of a self-paid transaction (without a paymaster).
+    pub max_prefund_cents: u64,
+    // ..and to the aliased accounts of a single source chain sender, per destination chain and (UTC) day.
+    pub max_prefund_per_sender_per_day_cents: u64,
+}
+
+// Prefunds sent to the aliased accounts of the self-paid transactions.
+#[derive(Default)]
+struct Prefunds {
+    // (destination chain, source chain, source chain sender) -> sent today.
+    spent: HashMap<(u64, u64, Address), DailySpend>,
+    // Messages whose accounts were prefunded - each message is prefunded at most once.
+    messages: HashSet<FixedBytes<32>>,
+}
+
+// Refuses the type C transactions that would cost the relayer without paying for it
+// (unknown paymasters or paymaster flows, huge gas limits or prices, no fee bundle,
+// spam from a single sender).
+pub struct Admission {
+    pub config: AdmissionConfig,
+    // (source chain, source chain sender) -> when its recent transactions were admitted.
+    recent: Mutex<HashMap<(u64, Address), VecDeque<Instant>>>,
+    prefunds: Mutex<Prefunds>,
+}
+
+impl Admission {
+    pub fn new(config: AdmissionConfig) -> Self {
+        Admission {
+            config,
+            recent: Mutex::new(HashMap::new()),
+            prefunds: Mutex::new(Prefunds::default()),
+        }
+    }
+
+    // Returns the reason, if the transaction must not be submitted.
+    // Retries of the already admitted transactions don't count towards the rate limit.
+    pub async fn check(
+        &self,
+        source_chain: u64,
+        interop_tx: &InteropTransaction,
+        destination_chain: u64,
+        preferred_paymaster: Address,
+        is_retry: bool,
+    ) -> Result<(), String> {
+        let paymaster = interop_tx.destinationPaymaster;
+        let self_paid = paymaster.is_zero();
+        let preferred = paymaster == preferred_paymaster;
+        if !self_paid && !preferred && !self.config.extra_paymasters.contains(&paymaster) {
+            return Err(format!(
+                "paymaster {} is not accepted on chain {}",
+                paymaster, destination_chain
+            ));
+        }
+        if interop_tx.gasLimit > self.config.max_gas_limit {
+            return Err(format!(
+                "gas limit {} is above {}",
+                interop_tx.gasLimit, self.config.max_gas_limit
+            ));
+        }
+        if interop_tx.gasPrice > self.config.max_gas_price {
+            return Err(format!(
+                "gas price {} is above {}",
+                interop_tx.gasPrice, self.config.max_gas_price
+            ));
+        }
+        // Other (allowed) paymasters might sponsor the transaction on their own.
+        if preferred && interop_tx.feesBundleHash.is_zero() {
+            return Err("paymaster sponsored transaction without a fee bundle".to_string());
+        }
+        // Fee bundle travels in the paymaster input.
+        if self_paid && !interop_tx.feesBundleHash.is_zero() {
+            return Err("fee bundle without a paymaster".to_string());
+        }
+        if self_paid && !interop_tx.destinationPaymasterInput.is_empty() {
+            return Err("paymaster input without a paymaster".to_string());
+        }
+        match PaymasterFlow::decode(&interop_tx.destinationPaymasterInput) {
+            // Aliased accounts don't approve anything in prepareForPaymaster - only CrossPaymaster
+            // takes its tokens without an allowance.
+            Ok(PaymasterFlow::ApprovalBased { .. }) if !preferred => {
+                return Err(format!(
+                    "approval based paymaster flow is supported only by the preferred paymaster on chain {}",
+                    destination_chain
+                ));
+            }
+            Ok(_) => {}
+            Err(reason) => return Err(reason.describe()),
+        }
+        if is_retry {
+            return Ok(());
+        }
+        self.rate_limit(source_chain, interop_tx.sourceChainSender, Instant::now())
+            .await
+    }
+
+    async fn rate_limit(
+        &self,
+        source_chain: u64,
+        sender: Address,
+        now: Instant,
+    ) -> Result<(), String> {
+        let mut recent = self.recent.lock().await;
+        let sent = recent.entry((source_chain, sender)).or_default();
+        while sent
+            .front()
+            .is_some_and(|sent_at| now.duration_since(*sent_at) > RATE_LIMIT_WINDOW)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= self.config.max_per_sender_per_minute {
+            return Err(format!(
+                "sender {} on chain {} sent more than {} transactions in the last minute",
+                sender, source_chain, self.config.max_per_sender_per_minute
+            ));
+        }
+        sent.push_back(now);
+        Ok(())
+    }
+
+    // Reserves the prefund of the message's aliased account, within the per sender daily limit.
+    // Returns the reason, if the account must not be prefunded.
+    pub async fn reserve_prefund(
+        &self,
+        msg_hash: FixedBytes<32>,
+        source_chain: u64,
+        sender: Address,
+        destination: &InteropChain,
+        amount: U256,
+    ) -> Result<(), String> {
+        let mut prefunds = self.prefunds.lock().await;
+        if prefunds.messages.contains(&msg_hash) {
+            return Err("aliased account was already prefunded for this message".to_string());
+        }
+        let daily_cap =
+            destination.cents_to_tokens(self.config.max_prefund_per_sender_per_day_cents);
+        let spend = prefunds
+            .spent
+            .entry((destination.chain_id, source_chain, sender))
+            .or_default();
+        spend.roll_over();
+        if spend.spent.saturating_add(amount) > daily_cap {
+            return Err(format!(
+                "sender {} on chain {} was already prefunded {} today on chain {}",
+                sender,
+                source_chain,
+                to_human_size(spend.spent),
+                destination.chain_id
+            ));
+        }
+        spend.spent += amount;
+        prefunds.messages.insert(msg_hash);
+        Ok(())
+    }
+
+    // Gives back the reservation of a prefund that was never sent.
+    pub async fn release_prefund(
+        &self,
+        msg_hash: FixedBytes<32>,
+        source_chain: u64,
+        sender: Address,
+        destination_chain: u64,
+        amount: U256,
+    ) {
+        let mut prefunds = self.prefunds.lock().await;
+        prefunds.messages.remove(&msg_hash);
+        if let Some(spend) = prefunds
+            .spent
+            .get_mut(&(destination_chain, source_chain, sender))
+        {
+            spend.spent = spend.spent.saturating_sub(amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{Bytes, B256};
+
+    const PREFERRED: Address = Address::repeat_byte(0xAA);
+    const EXTRA: Address = Address::repeat_byte(0xBB);
+    const SENDER: Address = Address::repeat_byte(0x11);
+
+    fn admission(max_per_sender_per_minute: usize) -> Admission {
+        Admission::new(AdmissionConfig {
+            extra_paymasters: HashSet::from([EXTRA]),
+            max_gas_limit: U256::from(1_000_000),
+            max_gas_price: U256::from(100),
+            max_per_sender_per_minute,
+            max_prefund_cents: 100,
+            max_prefund_per_sender_per_day_cents: 1_000,
+        })
+    }
+
+    fn interop_tx(paymaster: Address, fees_bundle_hash: B256) -> InteropTransaction {
+        InteropTransaction {
+            sourceChainSender: SENDER,
+            destinationChain: U256::from(501),
+            gasLimit: U256::from(1_000_000),
+            gasPrice: U256::from(100),
+            value: U256::ZERO,
+            bundleHash: B256::repeat_byte(1),
+            feesBundleHash: fees_bundle_hash,
+            destinationPaymaster: paymaster,
+            destinationPaymasterInput: Bytes::new(),
+        }
+    }
+
+    async fn check(admission: &Admission, interop_tx: &InteropTransaction) -> Result<(), String> {
+        admission
+            .check(500, interop_tx, 501, PREFERRED, false)
+            .await
+    }
+
+    #[tokio::test]
+    async fn paymasters() {
+        let admission = admission(100);
+        let fee_bundle = B256::repeat_byte(2);
+        assert_eq!(
+            check(&admission, &interop_tx(PREFERRED, fee_bundle)).await,
+            Ok(())
+        );
+        assert_eq!(
+            check(&admission, &interop_tx(EXTRA, fee_bundle)).await,
+            Ok(())
+        );
+        // Extra paymasters don't need the fee bundle.
+        assert_eq!(
+            check(&admission, &interop_tx(EXTRA, B256::ZERO)).await,
+            Ok(())
+        );
+        assert_eq!(
+            check(&admission, &interop_tx(Address::ZERO, B256::ZERO)).await,
+            Ok(())
+        );
+        let unknown = Address::repeat_byte(0xCC);
+        assert_eq!(
+            check(&admission, &interop_tx(unknown, fee_bundle)).await,
+            Err(format!(
+                "paymaster {} is not accepted on chain 501",
+                unknown
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn gas_caps() {
+        let admission = admission(100);
+        let mut tx = interop_tx(PREFERRED, B256::repeat_byte(2));
+        tx.gasLimit = U256::from(1_000_001);
+        assert_eq!(
+            check(&admission, &tx).await,
+            Err("gas limit 1000001 is above 1000000".to_string())
+        );
+
+        let mut tx = interop_tx(PREFERRED, B256::repeat_byte(2));
+        tx.gasPrice = U256::from(101);
+        assert_eq!(
+            check(&admission, &tx).await,
+            Err("gas price 101 is above 100".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn fee_bundle() {
+        let admission = admission(100);
+        assert_eq!(
+            check(&admission, &interop_tx(PREFERRED, B256::ZERO)).await,
+            Err("paymaster sponsored transaction without a fee bundle".to_string())
+        );
+        assert_eq!(
+            check(&admission, &interop_tx(Address::ZERO, B256::repeat_byte(2))).await,
+            Err("fee bundle without a paymaster".to_string())
+        );
+        let mut tx = interop_tx(Address::ZERO, B256::ZERO);
+        tx.destinationPaymasterInput = Bytes::from(vec![1]);
+        assert_eq!(
+            check(&admission, &tx).await,
+            Err("paymaster input without a paymaster".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn paymaster_flows() {
+        let admission = admission(100);
+        let approval_based = crate::paymaster::IPaymasterFlow::approvalBasedCall {
+            _token: Address::repeat_byte(0x77),
+            _minAllowance: U256::from(1),
+            _innerInput: Bytes::new(),
+        };
+        let mut tx = interop_tx(PREFERRED, B256::repeat_byte(2));
+        tx.destinationPaymasterInput =
+            alloy::sol_types::SolCall::abi_encode(&approval_based).into();
+        assert_eq!(check(&admission, &tx).await, Ok(()));
+
+        tx.destinationPaymaster = EXTRA;
+        assert_eq!(
+            check(&admission, &tx).await,
+            Err("approval based paymaster flow is supported only by the preferred paymaster on chain 501".to_string())
+        );
+
+        tx.destinationPaymasterInput = Bytes::from(vec![1, 2, 3]);
+        assert_eq!(
+            check(&admission, &tx).await,
+            Err(crate::revert::RevertReason::UnsupportedPaymasterFlow.describe())
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        let admission = admission(2);
+        let tx = interop_tx(PREFERRED, B256::repeat_byte(2));
+        let limited = Err(format!(
+            "sender {} on chain 500 sent more than 2 transactions in the last minute",
+            SENDER
+        ));
+        assert_eq!(check(&admission, &tx).await, Ok(()));
+        assert_eq!(check(&admission, &tx).await, Ok(()));
+        assert_eq!(check(&admission, &tx).await, limited);
+        // Retries are not limited.
+        assert_eq!(
+            admission.check(500, &tx, 501, PREFERRED, true).await,
+            Ok(())
+        );
+        // Other senders and chains have their own limit.
+        assert_eq!(
+            admission.check(502, &tx, 501, PREFERRED, false).await,
+            Ok(())
+        );
+        assert_eq!(
+            admission
+                .rate_limit(500, Address::repeat_byte(0x22), Instant::now())
+                .await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limit_window() {
+        let admission = admission(2);
+        let start = Instant::now();
+        assert_eq!(admission.rate_limit(500, SENDER, start).await, Ok(()));
+        let later = start + Duration::from_secs(30);
+        assert_eq!(admission.rate_limit(500, SENDER, later).await, Ok(()));
+        assert!(admission
+            .rate_limit(500, SENDER, start + RATE_LIMIT_WINDOW)
+            .await
+            .is_err());
+        // The first transaction left the window.
+        let after_window = start + RATE_LIMIT_WINDOW + Duration::from_secs(1);
+        assert_eq!(
+            admission.rate_limit(500, SENDER, after_window).await,
+            Ok(())
+        );
+        // ..but the second one didn't yet.
+        assert!(admission
+            .rate_limit(500, SENDER, after_window)
+            .await
+            .is_err());
+    }
+}
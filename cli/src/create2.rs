@@ -0,0 +1,68 @@
+use alloy::primitives::{keccak256, Address, B256};
+use sha2::{Digest, Sha256};
+
+// Versioned hash of the zkEVM bytecode (the one that ContractDeployer and the factory deps use).
+// sha256 of the bytecode, where the first 4 bytes are replaced by the version and the length in words.
+pub fn hash_bytecode(bytecode: &[u8]) -> anyhow::Result<B256> {
+    anyhow::ensure!(
+        bytecode.len() % 32 == 0,
+        "Bytecode length {} is not divisible by 32",
+        bytecode.len()
+    );
+    let words = bytecode.len() / 32;
+    anyhow::ensure!(
+        words < 1 << 16 && words % 2 == 1,
+        "Bytecode must have an odd number of words, below 2^16 (has {})",
+        words
+    );
+
+    let mut hash: [u8; 32] = Sha256::digest(bytecode).into();
+    hash[0] = 1;
+    hash[1] = 0;
+    hash[2..4].copy_from_slice(&(words as u16).to_be_bytes());
+    Ok(B256::from(hash))
+}
+
+// Address of the contract deployed with ContractDeployer.create2 - the same as _getZKSyncCreate2Address
+// in the InteropCenter.
+pub fn create2_address(
+    deployer: Address,
+    salt: B256,
+    bytecode_hash: B256,
+    constructor_input: &[u8],
+) -> Address {
+    let mut payload = Vec::with_capacity(160);
+    payload.extend_from_slice(keccak256("zksyncCreate2").as_slice());
+    payload.extend_from_slice(B256::left_padding_from(deployer.as_slice()).as_slice());
+    payload.extend_from_slice(salt.as_slice());
+    payload.extend_from_slice(bytecode_hash.as_slice());
+    payload.extend_from_slice(keccak256(constructor_input).as_slice());
+    Address::from_slice(&keccak256(payload)[12..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::b256;
+
+    #[test]
+    fn bytecode_hash() {
+        assert_eq!(
+            hash_bytecode(&[0; 32]).unwrap(),
+            b256!("01000001f862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925")
+        );
+        let bytecode: Vec<u8> = (1..=96).collect();
+        assert_eq!(
+            hash_bytecode(&bytecode).unwrap(),
+            b256!("0100000309d6a718960241fb8a688613360d372f228972cd037fbdb39bbf0bde")
+        );
+    }
+
+    #[test]
+    fn invalid_bytecode() {
+        // Not whole words, even number of words, too long.
+        assert!(hash_bytecode(&[0; 33]).is_err());
+        assert!(hash_bytecode(&[0; 64]).is_err());
+        assert!(hash_bytecode(&vec![0; 32 * (1 << 16)]).is_err());
+    }
+}
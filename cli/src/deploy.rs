@@ -0,0 +1,316 @@
+use crate::{
+    create2::{create2_address, hash_bytecode},
+    InteropCenter,
+};
+use alloy::{
+    hex::FromHex,
+    network::{ReceiptResponse, TransactionBuilder},
+    primitives::{address, Address, Bytes, B256, U256},
+    providers::Provider,
+    sol,
+    sol_types::{SolCall, SolValue},
+    transports::http::{Client, Http},
+};
+use alloy_zksync::{
+    network::{transaction_request::TransactionRequest, Zksync},
+    provider::zksync_provider,
+    wallet::ZksyncWallet,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+sol! {
+    interface IContractDeployer {
+        function create2(
+            bytes32 _salt,
+            bytes32 _bytecodeHash,
+            bytes calldata _input
+        ) external payable returns (address);
+    }
+}
+
+const CONTRACT_DEPLOYER: Address = address!("0000000000000000000000000000000000008006");
+
+// zkEVM artifact, as produced by 'forge build --zksync' (in zkout/).
+#[derive(Deserialize)]
+struct Artifact {
+    bytecode: ArtifactBytecode,
+    // Bytecode hash -> 'path:ContractName' of the contracts that this one deploys.
+    #[serde(default, rename = "factoryDependencies")]
+    factory_dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ArtifactBytecode {
+    object: String,
+}
+
+struct ContractToDeploy {
+    name: &'static str,
+    bytecode: Vec<u8>,
+    factory_deps: Vec<Vec<u8>>,
+    constructor_input: Vec<u8>,
+    bytecode_hash: B256,
+    address: Address,
+}
+
+// Written out after the deployment - contracts are at the same addresses on all the chains.
+#[derive(Debug, Serialize)]
+pub struct DeploymentConfig {
+    pub interop_center: Address,
+    pub paymaster: Address,
+    pub paymaster_token: Address,
+    pub chains: Vec<DeployedChain>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeployedChain {
+    pub chain_id: u64,
+    pub rpc: String,
+}
+
+// Artifacts embedded at build time (see build.rs) - empty if they were not built yet.
+macro_rules! embedded_artifact {
+    ($source_file:literal, $contract:literal) => {
+        (
+            $source_file,
+            $contract,
+            include_str!(concat!(
+                env!("OUT_DIR"),
+                "/zkout/",
+                $source_file,
+                "/",
+                $contract,
+                ".json"
+            )),
+        )
+    };
+}
+
+const EMBEDDED_ARTIFACTS: [(&str, &str, &str); 4] = [
+    embedded_artifact!("InteropCenter.sol", "InteropCenter"),
+    embedded_artifact!("InteropCenter.sol", "InteropAccount"),
+    embedded_artifact!("PaymasterToken.sol", "PaymasterToken"),
+    embedded_artifact!("CrossPaymaster.sol", "CrossPaymaster"),
+];
+
+fn embedded_artifact(source_file: &str, contract: &str) -> Option<&'static str> {
+    EMBEDDED_ARTIFACTS
+        .iter()
+        .find(|(file, name, _)| *file == source_file && *name == contract)
+        .map(|(_, _, content)| *content)
+        .filter(|content| !content.is_empty())
+}
+
+// From the artifacts directory, if given - otherwise the embedded one.
+fn load_artifact(
+    artifacts: Option<&Path>,
+    source_file: &str,
+    contract: &str,
+) -> anyhow::Result<Artifact> {
+    let content = match artifacts {
+        Some(artifacts) => {
+            let path = artifacts
+                .join(source_file)
+                .join(format!("{}.json", contract));
+            std::fs::read_to_string(&path).map_err(|error| {
+                anyhow::anyhow!("Failed to read artifact {}: {}", path.display(), error)
+            })?
+        }
+        None => embedded_artifact(source_file, contract)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Artifact {}/{} was not embedded at build time - pass --artifacts",
+                    source_file,
+                    contract
+                )
+            })?
+            .to_string(),
+    };
+    Ok(serde_json::from_str(&content)?)
+}
+
+impl ContractToDeploy {
+    fn load(
+        artifacts: Option<&Path>,
+        name: &'static str,
+        constructor_input: Vec<u8>,
+        deployer: Address,
+        salt: B256,
+    ) -> anyhow::Result<Self> {
+        let artifact = load_artifact(artifacts, &format!("{}.sol", name), name)?;
+        let bytecode = Vec::from_hex(artifact.bytecode.object.trim_start_matches("0x"))?;
+
+        let mut factory_deps = vec![];
+        for dependency in artifact.factory_dependencies.values() {
+            // 'src/InteropCenter.sol:InteropAccount'
+            let (path, contract) = dependency
+                .rsplit_once(':')
+                .ok_or_else(|| anyhow::anyhow!("Invalid factory dependency {}", dependency))?;
+            let source_file = Path::new(path).file_name().unwrap().to_string_lossy();
+            let dependency = load_artifact(artifacts, &source_file, contract)?;
+            factory_deps.push(Vec::from_hex(
+                dependency.bytecode.object.trim_start_matches("0x"),
+            )?);
+        }
+
+        let bytecode_hash = hash_bytecode(&bytecode)?;
+        let address = create2_address(deployer, salt, bytecode_hash, &constructor_input);
+        Ok(ContractToDeploy {
+            name,
+            bytecode,
+            factory_deps,
+            constructor_input,
+            bytecode_hash,
+            address,
+        })
+    }
+
+    async fn deploy<P>(&self, provider: &P, salt: B256) -> anyhow::Result<()>
+    where
+        P: Provider<Http<Client>, Zksync>,
+    {
+        if !provider.get_code_at(self.address).await?.is_empty() {
+            println!("  {} already deployed at {}", self.name, self.address);
+            return Ok(());
+        }
+
+        let calldata = IContractDeployer::create2Call {
+            _salt: salt,
+            _bytecodeHash: self.bytecode_hash,
+            _input: self.constructor_input.clone().into(),
+        };
+        let factory_deps: Vec<Bytes> = std::iter::once(&self.bytecode)
+            .chain(&self.factory_deps)
+            .map(|bytecode| bytecode.clone().into())
+            .collect();
+        let tx = TransactionRequest::default()
+            .with_to(CONTRACT_DEPLOYER)
+            .with_input(calldata.abi_encode())
+            .with_factory_deps(factory_deps);
+
+        let receipt = provider.send_transaction(tx).await?.get_receipt().await?;
+        anyhow::ensure!(
+            receipt.status(),
+            "Deployment of {} failed in tx {:?}",
+            self.name,
+            receipt.transaction_hash()
+        );
+        if let Some(deployed_at) = receipt.contract_address() {
+            anyhow::ensure!(
+                deployed_at == self.address,
+                "{} deployed at {}, expected {}",
+                self.name,
+                deployed_at,
+                self.address
+            );
+        }
+        anyhow::ensure!(
+            !provider.get_code_at(self.address).await?.is_empty(),
+            "No code at {} after deploying {}",
+            self.address,
+            self.name
+        );
+        println!(
+            "  Deployed {} at {} with tx {:?}",
+            self.name,
+            self.address,
+            receipt.transaction_hash()
+        );
+        Ok(())
+    }
+}
+
+// Versioned bytecode hash of the InteropAccount (aliased account) - deployed by the InteropCenter.
+// None, if there are neither artifacts given nor embedded (it is then read from the chains).
+pub fn interop_account_bytecode_hash(artifacts: Option<&Path>) -> anyhow::Result<Option<B256>> {
+    if artifacts.is_none() && embedded_artifact("InteropCenter.sol", "InteropAccount").is_none() {
+        return Ok(None);
+    }
+    let artifact = load_artifact(artifacts, "InteropCenter.sol", "InteropAccount")?;
+    Ok(Some(hash_bytecode(&Vec::from_hex(
+        artifact.bytecode.object.trim_start_matches("0x"),
+    )?)?))
+}
+
+// Deploys InteropCenter, PaymasterToken and CrossPaymaster with CREATE2 - so that they have the same addresses
+// on all the chains - and registers the paymaster as the preferred one for the local chain.
+pub async fn deploy(
+    urls: &[String],
+    artifacts: Option<&Path>,
+    salt: B256,
+    admin_wallet: ZksyncWallet,
+    admin_address: Address,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let interop_center =
+        ContractToDeploy::load(artifacts, "InteropCenter", vec![], admin_address, salt)?;
+    let paymaster_token = ContractToDeploy::load(
+        artifacts,
+        "PaymasterToken",
+        interop_center.address.abi_encode(),
+        admin_address,
+        salt,
+    )?;
+    let paymaster = ContractToDeploy::load(
+        artifacts,
+        "CrossPaymaster",
+        (paymaster_token.address, interop_center.address).abi_encode_params(),
+        admin_address,
+        salt,
+    )?;
+
+    let mut chains = vec![];
+    for url in urls {
+        let provider = zksync_provider()
+            .with_recommended_fillers()
+            .wallet(admin_wallet.clone())
+            .on_http(url.parse()?);
+        let chain_id = provider.get_chain_id().await?;
+        println!("Deploying to chain {} ({})", chain_id, url);
+
+        for contract in [&interop_center, &paymaster_token, &paymaster] {
+            contract.deploy(&provider, salt).await?;
+        }
+
+        let chain: U256 = chain_id.try_into().unwrap();
+        let contract = InteropCenter::new(interop_center.address, &provider);
+        if contract.preferredPaymasters(chain).call().await?._0 != paymaster.address {
+            let tx_hash = contract
+                .setPreferredPaymaster(chain, paymaster.address)
+                .send()
+                .await?
+                .watch()
+                .await?;
+            println!("  Registered preferred paymaster with tx {:?}", tx_hash);
+        }
+
+        chains.push(DeployedChain {
+            chain_id,
+            rpc: url.clone(),
+        });
+    }
+
+    let config = DeploymentConfig {
+        interop_center: interop_center.address,
+        paymaster: paymaster.address,
+        paymaster_token: paymaster_token.address,
+        chains,
+    };
+    let content = serde_json::to_string_pretty(&config)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, content)?;
+            println!("Deployment config written to {}", path.display());
+        }
+        None => println!("{}", content),
+    }
+
+    let rpc_args: Vec<_> = config
+        .chains
+        .iter()
+        .map(|chain| format!("-r {} {}", chain.rpc, config.interop_center))
+        .collect();
+    println!("Run the relayer with: {}", rpc_args.join(" "));
+    Ok(())
+}
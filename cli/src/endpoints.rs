@@ -0,0 +1,244 @@
+use alloy::providers::Provider;
+use alloy_zksync::provider::zksync_provider;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+pub type ChainProvider = alloy::providers::fillers::FillProvider<
+    alloy::providers::fillers::JoinFill<
+        alloy::providers::Identity,
+        alloy::providers::fillers::JoinFill<
+            alloy_zksync::provider::fillers::Eip712FeeFiller,
+            alloy::providers::fillers::JoinFill<
+                alloy::providers::fillers::NonceFiller,
+                alloy::providers::fillers::ChainIdFiller,
+            >,
+        >,
+    >,
+    alloy::providers::RootProvider<
+        alloy::transports::http::Http<reqwest::Client>,
+        alloy_zksync::network::Zksync,
+    >,
+    alloy::transports::http::Http<reqwest::Client>,
+    alloy_zksync::network::Zksync,
+>;
+
+// Requests that take longer than this are treated as failed (and we move to the next endpoint).
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+// Endpoints that are more than this many blocks behind the best one are considered unhealthy.
+const MAX_BLOCKS_BEHIND: u64 = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EndpointError {
+    #[error("all {0} endpoints failed, last error: {1}")]
+    AllFailed(usize, String),
+    #[error("no quorum - needed {needed} matching responses, got: {responses:?}")]
+    NoQuorum {
+        needed: usize,
+        responses: Vec<String>,
+    },
+}
+
+pub struct Endpoint {
+    pub url: String,
+    pub provider: ChainProvider,
+    pub healthy: AtomicBool,
+    // Moving average of the request latency.
+    pub latency_ms: AtomicU64,
+    // Head block, as seen during the last health check.
+    pub last_block: AtomicU64,
+    pub last_success: std::sync::Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn record_success(&self, latency: Duration) {
+        let latency = latency.as_millis() as u64;
+        let previous = self.latency_ms.load(Ordering::Relaxed);
+        let average = if previous == 0 {
+            latency
+        } else {
+            (previous * 4 + latency) / 5
+        };
+        self.latency_ms.store(average, Ordering::Relaxed);
+        self.healthy.store(true, Ordering::Relaxed);
+        *self.last_success.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        if self.healthy.swap(false, Ordering::Relaxed) {
+            println!("!! Endpoint {} marked as unhealthy: {}", self.url, error);
+        }
+    }
+}
+
+// All the RPC endpoints of a single chain.
+pub struct Endpoints {
+    pub endpoints: Vec<Endpoint>,
+    // How many endpoints must agree on the result of the critical reads.
+    pub quorum: usize,
+}
+
+impl Endpoints {
+    pub fn new(urls: &[String], quorum: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(!urls.is_empty(), "Each chain needs at least one RPC url");
+        anyhow::ensure!(
+            quorum <= urls.len(),
+            "Quorum {} is larger than the number of endpoints {:?}",
+            quorum,
+            urls
+        );
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for url in urls {
+            let parsed = url
+                .parse()
+                .map_err(|err| anyhow::anyhow!("Invalid RPC url {:?}: {}", url, err))?;
+            endpoints.push(Endpoint {
+                url: url.clone(),
+                provider: zksync_provider().with_recommended_fillers().on_http(parsed),
+                healthy: AtomicBool::new(true),
+                latency_ms: AtomicU64::new(0),
+                last_block: AtomicU64::new(0),
+                last_success: std::sync::Mutex::new(None),
+            });
+        }
+        Ok(Endpoints { endpoints, quorum })
+    }
+
+    // Healthy endpoints first (fastest first), then the unhealthy ones.
+    fn by_preference(&self) -> Vec<&Endpoint> {
+        let mut endpoints: Vec<_> = self.endpoints.iter().collect();
+        endpoints.sort_by_key(|endpoint| {
+            (
+                !endpoint.healthy.load(Ordering::Relaxed),
+                endpoint.latency_ms.load(Ordering::Relaxed),
+            )
+        });
+        endpoints
+    }
+
+    pub fn active(&self) -> &Endpoint {
+        self.by_preference()[0]
+    }
+
+    pub fn last_success(&self) -> Option<Instant> {
+        self.endpoints
+            .iter()
+            .filter_map(|endpoint| *endpoint.last_success.lock().unwrap())
+            .max()
+    }
+
+    async fn call_endpoint<T, E, F, Fut>(&self, endpoint: &Endpoint, f: &F) -> Result<T, String>
+    where
+        F: Fn(ChainProvider) -> Fut,
+        Fut: futures::future::Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(REQUEST_TIMEOUT, f(endpoint.provider.clone())).await
+        {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err(format!("timeout after {:?}", REQUEST_TIMEOUT)),
+        };
+        match &result {
+            Ok(_) => endpoint.record_success(start.elapsed()),
+            Err(error) => endpoint.record_failure(error),
+        }
+        result
+    }
+
+    // Runs the request on the preferred endpoint, failing over to the other ones on errors.
+    pub async fn call<T, E, F, Fut>(&self, f: F) -> Result<T, EndpointError>
+    where
+        F: Fn(ChainProvider) -> Fut,
+        Fut: futures::future::Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let mut last_error = String::new();
+        for endpoint in self.by_preference() {
+            match self.call_endpoint(endpoint, &f).await {
+                Ok(result) => return Ok(result),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(EndpointError::AllFailed(self.endpoints.len(), last_error))
+    }
+
+    // Runs the request on all the endpoints, and returns the result only if at least 'quorum' of them agree.
+    pub async fn quorum_call<T, E, F, Fut>(&self, f: F) -> Result<T, EndpointError>
+    where
+        T: PartialEq + Debug,
+        F: Fn(ChainProvider) -> Fut,
+        Fut: futures::future::Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        if self.quorum <= 1 {
+            return self.call(f).await;
+        }
+        let results = futures::future::join_all(
+            self.endpoints
+                .iter()
+                .map(|endpoint| self.call_endpoint(endpoint, &f)),
+        )
+        .await;
+
+        let mut responses = vec![];
+        let mut votes: Vec<(T, usize)> = vec![];
+        for result in results {
+            responses.push(format!("{:?}", result));
+            if let Ok(value) = result {
+                match votes.iter_mut().find(|(existing, _)| *existing == value) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((value, 1)),
+                }
+            }
+        }
+        match votes.into_iter().find(|(_, count)| *count >= self.quorum) {
+            Some((value, _)) => Ok(value),
+            None => Err(EndpointError::NoQuorum {
+                needed: self.quorum,
+                responses,
+            }),
+        }
+    }
+
+    pub async fn check_health(&self) {
+        let mut heads = HashMap::new();
+        for endpoint in &self.endpoints {
+            if let Ok(block) = self
+                .call_endpoint(endpoint, &|provider: ChainProvider| async move {
+                    provider.get_block_number().await
+                })
+                .await
+            {
+                endpoint.last_block.store(block, Ordering::Relaxed);
+                heads.insert(endpoint.url.clone(), block);
+            }
+        }
+        let best = heads.values().copied().max().unwrap_or_default();
+        for endpoint in &self.endpoints {
+            if let Some(block) = heads.get(&endpoint.url) {
+                if block + MAX_BLOCKS_BEHIND < best {
+                    endpoint.record_failure(&format!(
+                        "stalled at block {} (best known is {})",
+                        block, best
+                    ));
+                }
+            }
+        }
+    }
+
+    pub async fn run_health_checks(self: Arc<Self>) {
+        loop {
+            self.check_health().await;
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use alloy::primitives::{FixedBytes, B256, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeKind {
+    // Type C transaction - gas is paid by the paymaster, that collects the fee tokens from the user.
+    TypeC,
+    // Aliased account deployed by the relayer (admin wallet) before the type C transaction.
+    AliasedAccountDeployment,
+    // receiveInteropMessage sent by the relayer (admin wallet).
+    Forward,
+    // Base token sent to the aliased account, for a type C transaction without a paymaster.
+    AliasedAccountPrefund,
+}
+
+// Single transaction on the destination chain, that the relayer (or its paymaster) paid for.
+// Amounts are in the base tokens of the destination chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRecord {
+    pub timestamp: u64,
+    pub msg_hash: FixedBytes<32>,
+    pub source_chain: u64,
+    pub destination_chain: u64,
+    pub kind: FeeKind,
+    pub tx_hash: B256,
+    // Gas used * effective gas price.
+    pub cost: U256,
+    // Paymaster tokens (1:1 with the base token) that the paymaster took from the user.
+    pub collected: U256,
+    // Price of the destination base token (10^18) in cents, when the transaction was included.
+    pub base_token_price: u64,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// Value of the amount of base tokens, in hundredths of a cent.
+fn to_centi_cents(amount: U256, base_token_price: u64) -> i128 {
+    let base_token = U256::from(1_000_000_000_000_000_000u64);
+    let value = amount
+        .saturating_mul(U256::from(base_token_price))
+        .saturating_mul(U256::from(100))
+        / base_token;
+    i128::try_from(value).unwrap_or(i128::MAX)
+}
+
+// Appends the fee records to a JSON lines file (if configured) - read by 'report fees'.
+pub struct FeeLedger {
+    path: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl FeeLedger {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        FeeLedger {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub async fn record(&self, record: FeeRecord) {
+        println!(
+            "  Fees for {:?} ({:?} {} -> {}): cost {} collected {} (price {} cents)",
+            record.msg_hash,
+            record.kind,
+            record.source_chain,
+            record.destination_chain,
+            record.cost,
+            record.collected,
+            record.base_token_price
+        );
+        let Some(path) = &self.path else {
+            return;
+        };
+        let _lock = self.lock.lock().await;
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&record).unwrap()));
+        if let Err(error) = result {
+            println!(
+                "!! Failed to write fee record to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+pub fn load_records(path: &Path) -> anyhow::Result<Vec<FeeRecord>> {
+    let mut records = vec![];
+    for (index, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(line).map_err(|error| {
+            anyhow::anyhow!(
+                "{}:{}: invalid fee record: {}",
+                path.display(),
+                index + 1,
+                error
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// Parses --since: either a unix timestamp, or a duration ago (e.g. 30m, 12h, 7d).
+pub fn parse_since(since: &str) -> anyhow::Result<u64> {
+    if let Ok(timestamp) = since.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid --since '{}' - use e.g. 12h, 7d or a unix timestamp",
+            since
+        )
+    };
+    let unit = since.chars().last().ok_or_else(invalid)?;
+    let value: u64 = since[..since.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let unit_seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => anyhow::bail!("Invalid --since unit '{}' - use s, m, h or d", unit),
+    };
+    let seconds = value.checked_mul(unit_seconds).ok_or_else(invalid)?;
+    Ok(now().saturating_sub(seconds))
+}
+
+#[derive(Default)]
+struct PairSummary {
+    type_c: usize,
+    deployments: usize,
+    forwards: usize,
+    prefunds: usize,
+    cost: i128,
+    collected: i128,
+}
+
+fn format_cents(centi_cents: i128) -> String {
+    format!("{:.2}", centi_cents as f64 / 100.0)
+}
+
+// Totals per source -> destination chain pair, of the records since the given timestamp.
+fn summarize(records: Vec<FeeRecord>, since: u64) -> BTreeMap<(u64, u64), PairSummary> {
+    let mut pairs: BTreeMap<(u64, u64), PairSummary> = BTreeMap::new();
+    for record in records {
+        if record.timestamp < since {
+            continue;
+        }
+        let summary = pairs
+            .entry((record.source_chain, record.destination_chain))
+            .or_default();
+        match record.kind {
+            FeeKind::TypeC => summary.type_c += 1,
+            FeeKind::AliasedAccountDeployment => summary.deployments += 1,
+            FeeKind::Forward => summary.forwards += 1,
+            FeeKind::AliasedAccountPrefund => summary.prefunds += 1,
+        }
+        summary.cost += to_centi_cents(record.cost, record.base_token_price);
+        summary.collected += to_centi_cents(record.collected, record.base_token_price);
+    }
+    pairs
+}
+
+// Prints the profit / loss (in cents) per source -> destination chain pair.
+pub fn report(path: &Path, since: u64) -> anyhow::Result<()> {
+    let pairs = summarize(load_records(path)?, since);
+
+    println!("Fees since {} (amounts in cents):", since);
+    println!(
+        "{:>8} {:>8} {:>7} {:>8} {:>9} {:>9} {:>12} {:>12} {:>12}",
+        "source",
+        "dest",
+        "type C",
+        "deploys",
+        "forwards",
+        "prefunds",
+        "cost",
+        "collected",
+        "profit"
+    );
+    let mut total = PairSummary::default();
+    for ((source_chain, destination_chain), summary) in &pairs {
+        println!(
+            "{:>8} {:>8} {:>7} {:>8} {:>9} {:>9} {:>12} {:>12} {:>12}",
+            source_chain,
+            destination_chain,
+            summary.type_c,
+            summary.deployments,
+            summary.forwards,
+            summary.prefunds,
+            format_cents(summary.cost),
+            format_cents(summary.collected),
+            format_cents(summary.collected - summary.cost)
+        );
+        total.cost += summary.cost;
+        total.collected += summary.collected;
+    }
+    println!(
+        "Total: cost {} collected {} profit {}",
+        format_cents(total.cost),
+        format_cents(total.collected),
+        format_cents(total.collected - total.cost)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH: u64 = 1_000_000_000_000_000_000;
+
+    fn record(
+        timestamp: u64,
+        source_chain: u64,
+        kind: FeeKind,
+        cost: U256,
+        collected: U256,
+    ) -> FeeRecord {
+        FeeRecord {
+            timestamp,
+            msg_hash: FixedBytes::repeat_byte(1),
+            source_chain,
+            destination_chain: 501,
+            kind,
+            tx_hash: B256::repeat_byte(2),
+            cost,
+            collected,
+            // 2000 USD.
+            base_token_price: 200_000,
+        }
+    }
+
+    #[test]
+    fn centi_cents() {
+        assert_eq!(to_centi_cents(U256::from(ETH), 200_000), 20_000_000);
+        assert_eq!(to_centi_cents(U256::from(ETH / 1_000), 200_000), 20_000);
+        assert_eq!(to_centi_cents(U256::ZERO, 200_000), 0);
+        assert_eq!(to_centi_cents(U256::from(ETH), 0), 0);
+        // Less than a hundredth of a cent is rounded down.
+        assert_eq!(to_centi_cents(U256::from(1), 200_000), 0);
+        assert_eq!(to_centi_cents(U256::from(ETH / 20_000_000), 200_000), 1);
+        assert_eq!(to_centi_cents(U256::MAX, 200_000), i128::MAX);
+    }
+
+    #[test]
+    fn formats_cents() {
+        assert_eq!(format_cents(20_000_000), "200000.00");
+        assert_eq!(format_cents(150), "1.50");
+        assert_eq!(format_cents(-25), "-0.25");
+        assert_eq!(format_cents(0), "0.00");
+    }
+
+    #[test]
+    fn since_timestamp() {
+        assert_eq!(parse_since("1700000000").unwrap(), 1_700_000_000);
+        assert_eq!(parse_since("0").unwrap(), 0);
+    }
+
+    #[test]
+    fn since_duration() {
+        for (since, seconds) in [
+            ("30s", 30),
+            ("30m", 30 * 60),
+            ("12h", 12 * 60 * 60),
+            ("7d", 7 * 24 * 60 * 60),
+        ] {
+            let before = now();
+            let timestamp = parse_since(since).unwrap();
+            assert!(timestamp >= before - seconds && timestamp <= now() - seconds);
+        }
+        // Further back than the epoch.
+        assert_eq!(parse_since("100000000d").unwrap(), 0);
+    }
+
+    #[test]
+    fn since_errors() {
+        for since in [
+            "",
+            "h",
+            "-5h",
+            "1.5h",
+            "twelve hours",
+            "99999999999999999999d",
+        ] {
+            assert_eq!(
+                parse_since(since).unwrap_err().to_string(),
+                format!(
+                    "Invalid --since '{}' - use e.g. 12h, 7d or a unix timestamp",
+                    since
+                )
+            );
+        }
+        assert_eq!(
+            parse_since("12w").unwrap_err().to_string(),
+            "Invalid --since unit 'w' - use s, m, h or d"
+        );
+        assert_eq!(
+            parse_since("12é").unwrap_err().to_string(),
+            "Invalid --since unit 'é' - use s, m, h or d"
+        );
+        // Doesn't fit u64 seconds.
+        assert!(parse_since(&format!("{}d", u64::MAX / 1000)).is_err());
+    }
+
+    #[test]
+    fn summarize_pairs() {
+        let cent = U256::from(ETH / 200_000);
+        let records = vec![
+            record(
+                100,
+                500,
+                FeeKind::TypeC,
+                cent * U256::from(3),
+                cent * U256::from(5),
+            ),
+            record(
+                101,
+                500,
+                FeeKind::AliasedAccountDeployment,
+                cent * U256::from(10),
+                U256::ZERO,
+            ),
+            record(102, 500, FeeKind::AliasedAccountPrefund, cent, U256::ZERO),
+            record(103, 502, FeeKind::Forward, cent * U256::from(2), U256::ZERO),
+            // Before --since.
+            record(
+                99,
+                500,
+                FeeKind::TypeC,
+                cent * U256::from(1_000),
+                U256::ZERO,
+            ),
+        ];
+        let pairs = summarize(records, 100);
+        assert_eq!(pairs.len(), 2);
+
+        let summary = &pairs[&(500, 501)];
+        assert_eq!(
+            (
+                summary.type_c,
+                summary.deployments,
+                summary.forwards,
+                summary.prefunds
+            ),
+            (1, 1, 0, 1)
+        );
+        assert_eq!(summary.cost, 1_400);
+        assert_eq!(summary.collected, 500);
+
+        let summary = &pairs[&(502, 501)];
+        assert_eq!(summary.forwards, 1);
+        assert_eq!(summary.cost, 200);
+        assert_eq!(summary.collected, 0);
+    }
+
+    #[test]
+    fn load_invalid_records() {
+        let path = std::env::temp_dir().join(format!("fees-test-{}.jsonl", std::process::id()));
+        let valid =
+            serde_json::to_string(&record(100, 500, FeeKind::TypeC, U256::from(1), U256::ZERO))
+                .unwrap();
+        std::fs::write(&path, format!("{}\n\n{}\n", valid, valid)).unwrap();
+        assert_eq!(load_records(&path).unwrap().len(), 2);
+
+        std::fs::write(&path, format!("{}\nnot json\n", valid)).unwrap();
+        let error = load_records(&path).unwrap_err().to_string();
+        assert!(error.starts_with(&format!("{}:2: invalid fee record", path.display())));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
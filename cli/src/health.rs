@@ -0,0 +1,159 @@
+use crate::{InteropChain, SharedChains};
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// How far (in blocks, on top of the confirmations) the listener can be behind the head and still be 'ready'.
+const MAX_READY_LAG: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenerStatus {
+    Starting,
+    // Processing the logs from the past (or since the checkpoint).
+    CatchingUp,
+    Running,
+    // Paused from the admin API.
+    Paused,
+    Stopped,
+}
+
+pub struct HealthState {
+    pub providers_map: SharedChains,
+    // Set once the trust and paymaster setup was checked against the on-chain state.
+    pub wiring_verified: AtomicBool,
+}
+
+#[derive(Serialize)]
+struct ChainHealth {
+    chain_id: u64,
+    listener: ListenerStatus,
+    last_processed_block: u64,
+    chain_head: u64,
+    healthy_endpoints: usize,
+    total_endpoints: usize,
+    seconds_since_last_rpc_success: Option<u64>,
+    signer_balance: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HealthReport {
+    ok: bool,
+    wiring_verified: bool,
+    chains: Vec<ChainHealth>,
+}
+
+impl ChainHealth {
+    fn is_alive(&self) -> bool {
+        self.listener != ListenerStatus::Stopped && self.healthy_endpoints > 0
+    }
+
+    fn is_ready(&self, confirmations: u64) -> bool {
+        self.is_alive()
+            && self.listener == ListenerStatus::Running
+            && self.chain_head <= self.last_processed_block + confirmations + MAX_READY_LAG
+    }
+}
+
+async fn chain_health(chain: &InteropChain, with_balance: bool) -> ChainHealth {
+    let endpoints = &chain.endpoints.endpoints;
+    let signer_balance = if with_balance {
+        // Don't let a slow RPC block the probe.
+        tokio::time::timeout(
+            Duration::from_secs(2),
+            chain.get_balance(chain.admin_address),
+        )
+        .await
+        .ok()
+        .and_then(|balance| balance.ok())
+        .map(|balance| balance.to_string())
+    } else {
+        None
+    };
+
+    ChainHealth {
+        chain_id: chain.chain_id,
+        listener: *chain.listener_status.lock().unwrap(),
+        last_processed_block: chain.last_processed_block.load(Ordering::Relaxed),
+        chain_head: endpoints
+            .iter()
+            .map(|endpoint| endpoint.last_block.load(Ordering::Relaxed))
+            .max()
+            .unwrap_or_default(),
+        healthy_endpoints: endpoints
+            .iter()
+            .filter(|endpoint| endpoint.healthy.load(Ordering::Relaxed))
+            .count(),
+        total_endpoints: endpoints.len(),
+        seconds_since_last_rpc_success: chain
+            .endpoints
+            .last_success()
+            .map(|instant| instant.elapsed().as_secs()),
+        signer_balance,
+    }
+}
+
+async fn report(
+    state: &HealthState,
+    with_balance: bool,
+    check: impl Fn(&ChainHealth, &InteropChain) -> bool,
+) -> (StatusCode, Json<HealthReport>) {
+    let mut chains = vec![];
+    let mut ok = true;
+    let providers: Vec<_> = state.providers_map.read().await.values().cloned().collect();
+    for chain in providers {
+        let health = chain_health(&chain, with_balance).await;
+        ok &= check(&health, chain.as_ref());
+        chains.push(health);
+    }
+    chains.sort_by_key(|chain| chain.chain_id);
+
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(HealthReport {
+            ok,
+            wiring_verified: state.wiring_verified.load(Ordering::Relaxed),
+            chains,
+        }),
+    )
+}
+
+// Liveness - listeners are still running and can reach at least one endpoint.
+async fn healthz(State(state): State<Arc<HealthState>>) -> (StatusCode, Json<HealthReport>) {
+    report(&state, false, |health, _| health.is_alive()).await
+}
+
+// Readiness - startup verification passed, all the listeners are caught up with their chains
+// and the signer balance can be read.
+async fn readyz(State(state): State<Arc<HealthState>>) -> (StatusCode, Json<HealthReport>) {
+    let wiring_verified = state.wiring_verified.load(Ordering::Relaxed);
+    report(&state, true, |health, chain| {
+        wiring_verified && health.is_ready(chain.confirmations) && health.signer_balance.is_some()
+    })
+    .await
+}
+
+pub fn health_routes(state: Arc<HealthState>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
+
+pub async fn serve(address: SocketAddr, router: Router) {
+    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+    println!("HTTP server listening on {}", address);
+    axum::serve(listener, router).await.unwrap();
+}
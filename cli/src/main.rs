@@ -1,14 +1,36 @@
+mod accounts;
+mod admin;
+mod admission;
+mod create2;
+mod deploy;
+mod endpoints;
+mod fees;
+mod health;
+mod membership;
+mod paymaster;
+mod policy;
+mod pricing;
+mod rebalance;
+mod revert;
+mod setup;
+mod shutdown;
+mod state;
+mod topology;
+mod treasury;
+mod validate;
+
 use alloy::{
     consensus::Signed,
     dyn_abi::SolType,
     hex::FromHex,
-    network::TransactionBuilder,
-    primitives::{Address, Bytes, FixedBytes, B256, U256},
+    network::{ReceiptResponse, TransactionBuilder},
+    primitives::{keccak256, Address, Bytes, FixedBytes, B256, U256},
     providers::Provider,
     rlp::BytesMut,
     rpc::types::{Filter, Log},
     signers::local::PrivateKeySigner,
     sol_types::{SolCall, SolEvent},
+    transports::{RpcError, TransportErrorKind},
 };
 use alloy_zksync::{
     network::{
@@ -23,19 +45,42 @@ use alloy::network::eip2718::Encodable2718;
 
 use alloy::signers::Signature;
 use k256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
 
 use alloy::sol;
 
-use clap::Parser;
+use accounts::AccountManager;
+use admin::AddChainRequest;
+use admission::{Admission, AdmissionConfig};
+use clap::{Parser, Subcommand};
+use endpoints::{ChainProvider, EndpointError, Endpoints};
+use fees::{FeeKind, FeeLedger, FeeRecord};
+use futures::future::BoxFuture;
 use futures_util::stream::StreamExt;
+use health::{health_routes, HealthState, ListenerStatus};
+use policy::TrustPolicy;
+use pricing::{FilePrices, HttpPrices, PriceSource, PriceUpdateConfig, StaticPrices};
+use rebalance::RebalanceConfig;
+use revert::{RevertAction, RevertReason};
+use shutdown::{wait_for_shutdown_signal, InFlight};
+use state::RelayerState;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::{Debug, Formatter},
+    net::SocketAddr,
+    path::PathBuf,
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use tokio::sync::Mutex;
-use InteropCenter::InteropMessage;
+use tokio::{
+    sync::{watch, Mutex, RwLock},
+    task::JoinHandle,
+};
+use treasury::{Treasury, TreasuryConfig};
 
 sol! {
     #[sol(rpc)]
@@ -70,6 +115,7 @@ sol! {
             address sourceAccount,
             uint256 sourceChainId
         ) public returns (address);
+        function aliasedAccountBytecodeHash() public pure returns (bytes32);
 
 
         function executeInteropBundle(
@@ -123,6 +169,7 @@ sol! {
     #[sol(rpc)]
     contract CrossPaymaster {
         address public paymasterTokenAddress;
+        function withdrawTokens(address to, uint256 amount) external;
     }
 
     #[sol(rpc)]
@@ -136,9 +183,44 @@ sol! {
             uint256 ratioNominator,
             uint256 ratioDenominator
         ) public;
+        function removeOtherBridge(uint256 sourceChainId) public;
+        event Transfer(address indexed from, address indexed to, uint256 value);
+        function balanceOf(address account) public view returns (uint256);
+        function transfer(address to, uint256 value) public returns (bool);
+        function sendToRemoteInNewBundle(
+            uint256 destinationChainId,
+            address remoteRecipient,
+            uint256 amount
+        ) public returns (bytes32);
+        function redeem(uint256 amount) public;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "reason")]
+pub enum MessageStatus {
+    // Seen, but not fully processed yet.
+    Pending,
+    Relayed,
+    Failed(String),
+    // Simulation failed with an error that might go away on its own - will be retried.
+    Retrying(String),
+    // User's gas limit or gas price is not enough for the current fees on the destination chain.
+    Underpriced(String),
+    // Not relayed, as the trust policy doesn't allow messages between the chains.
+    Refused(String),
+    // Skipped on operator's request.
+    Skipped,
+}
+
+impl MessageStatus {
+    // Pending and Retrying messages still have some work left.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, MessageStatus::Pending | MessageStatus::Retrying(_))
     }
 }
 
+#[derive(Clone)]
 pub struct InteropMessageParsed {
     pub interop_center_sender: Address,
     // The unique global identifier of this message.
@@ -150,7 +232,18 @@ pub struct InteropMessageParsed {
     pub data: Bytes,
 
     pub interop_message: InteropCenter::InteropMessage,
+    // Transaction carried by the message (type C only).
+    pub interop_transaction: Option<InteropCenter::InteropTransaction>,
     pub chain_id: u64,
+    // Block on the source chain that emitted this message.
+    pub block_number: Option<u64>,
+
+    // Set when the log that emitted this message was removed from the source chain by a reorg.
+    pub source_log_removed: bool,
+
+    pub status: MessageStatus,
+    // How many times we tried to process this message.
+    pub attempts: u32,
 }
 
 impl Debug for InteropMessageParsed {
@@ -160,137 +253,219 @@ impl Debug for InteropMessageParsed {
             .field("msg_hash", &self.msg_hash)
             .field("sender", &self.sender)
             .field("data", &self.data)
+            .field("block_number", &self.block_number)
+            .field("source_log_removed", &self.source_log_removed)
+            .field("status", &self.status)
+            .field("attempts", &self.attempts)
             .finish()
     }
 }
 
+// Reasons for rejecting an InteropMessageSent log - they are not relayed.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidLogError {
+    #[error("failed to decode InteropMessageSent: {0}")]
+    Event(alloy::sol_types::Error),
+    #[error("failed to decode the interop message from the payload: {0}")]
+    Payload(alloy::sol_types::Error),
+    #[error("msgHash {indexed:?} doesn't match keccak256(payload) {computed:?}")]
+    HashMismatch { indexed: B256, computed: B256 },
+    #[error("indexed sender {indexed} doesn't match the message sender {message}")]
+    SenderMismatch { indexed: Address, message: Address },
+    #[error("message claims to come from chain {message}, but was emitted on chain {actual}")]
+    ChainMismatch { message: U256, actual: u64 },
+    #[error("failed to decode the interop transaction from the payload: {0}")]
+    Transaction(alloy::sol_types::Error),
+    #[error("invalid destination chain {0}")]
+    DestinationChain(U256),
+}
+
+// Log that failed the integrity checks.
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedLog {
+    pub chain_id: u64,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<B256>,
+    pub log_index: Option<u64>,
+    pub reason: String,
+}
+
 impl InteropMessageParsed {
-    pub fn from_log(log: &Log, chain_id: u64) -> Self {
-        let interop_message =
-            InteropCenter::InteropMessage::abi_decode(&log.data().data.slice(64..), true).unwrap();
-
-        InteropMessageParsed {
-            interop_center_sender: log.address(),
-            msg_hash: log.topics()[1],
-            sender: Address::from_slice(&log.topics()[2].0[12..]),
+    pub fn from_log(log: &Log, chain_id: u64) -> Result<Self, InvalidLogError> {
+        let event = InteropCenter::InteropMessageSent::decode_log(&log.inner, true)
+            .map_err(InvalidLogError::Event)?;
+        let payload = &event.payload;
+
+        let computed = keccak256(payload);
+        if computed != event.msgHash {
+            return Err(InvalidLogError::HashMismatch {
+                indexed: event.msgHash,
+                computed,
+            });
+        }
+        let interop_message = InteropCenter::InteropMessage::abi_decode(payload, true)
+            .map_err(InvalidLogError::Payload)?;
+        if interop_message.sender != event.sender {
+            return Err(InvalidLogError::SenderMismatch {
+                indexed: event.sender,
+                message: interop_message.sender,
+            });
+        }
+        if interop_message.sourceChainId != U256::from(chain_id) {
+            return Err(InvalidLogError::ChainMismatch {
+                message: interop_message.sourceChainId,
+                actual: chain_id,
+            });
+        }
+        let interop_transaction =
+            if event.address == event.sender && interop_message.data.first() == Some(&2) {
+                let interop_tx =
+                    InteropCenter::InteropTransaction::abi_decode(&interop_message.data[1..], true)
+                        .map_err(InvalidLogError::Transaction)?;
+                if u64::try_from(interop_tx.destinationChain).is_err() {
+                    return Err(InvalidLogError::DestinationChain(
+                        interop_tx.destinationChain,
+                    ));
+                }
+                Some(interop_tx)
+            } else {
+                None
+            };
+
+        Ok(InteropMessageParsed {
+            interop_center_sender: event.address,
+            msg_hash: event.msgHash,
+            sender: event.sender,
             data: log.data().data.clone(),
             interop_message,
+            interop_transaction,
             chain_id,
-        }
+            block_number: log.block_number,
+            source_log_removed: log.removed,
+            status: MessageStatus::Pending,
+            attempts: 0,
+        })
     }
 
     pub fn is_type_b(&self) -> bool {
-        return self.interop_center_sender == self.sender && self.interop_message.data[0] == 1;
+        return self.interop_center_sender == self.sender
+            && self.interop_message.data.first() == Some(&1);
     }
     pub fn is_type_c(&self) -> bool {
-        return self.interop_center_sender == self.sender && self.interop_message.data[0] == 2;
+        return self.interop_center_sender == self.sender
+            && self.interop_message.data.first() == Some(&2);
     }
 
     pub async fn create_transaction_request(
         &self,
-        providers_map: &HashMap<u64, Arc<InteropChain>>,
+        interop_tx: &InteropCenter::InteropTransaction,
+        destination_interop_chain: &InteropChain,
         all_messages: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>>,
-    ) -> Option<(u64, TransactionRequest)> {
-        let interop_tx =
-            InteropCenter::InteropTransaction::abi_decode(&self.interop_message.data[1..], true)
-                .unwrap();
-
+        accounts: &AccountManager,
+        fee_ledger: &FeeLedger,
+    ) -> Result<Option<TransactionRequest>, RevertReason> {
         println!("Interop TX: destination: {}", interop_tx.destinationChain);
 
-        let destination_chain_id: u64 = interop_tx.destinationChain.try_into().unwrap();
-        let destination_interop_chain = providers_map.get(&destination_chain_id).unwrap();
-
         if destination_interop_chain
             .is_bundle_executed(interop_tx.bundleHash)
-            .await
+            .await?
         {
             println!("    Bundle is already executed");
-            return None;
+            return Ok(None);
         }
 
         if !interop_tx.feesBundleHash.is_zero()
             && destination_interop_chain
                 .is_bundle_executed(interop_tx.feesBundleHash)
-                .await
+                .await?
         {
             println!("    Fee Bundle is already executed");
-            return None;
+            return Ok(None);
         }
 
-        let from_addr = destination_interop_chain
-            .get_aliased_account_address(
-                // TODO: Or provided from the RPC??
-                self.interop_message.sourceChainId,
+        // Checked before anything is sent - the paymaster would reject it anyway.
+        let paymaster_input = if interop_tx.destinationPaymaster.is_zero() {
+            vec![]
+        } else {
+            let fee_message = if !interop_tx.feesBundleHash.is_zero() {
+                println!("  Fee Bundle is present");
+                Some(
+                    all_messages
+                        .lock()
+                        .await
+                        .get(&interop_tx.feesBundleHash)
+                        .ok_or(RevertReason::MissingBundle(interop_tx.feesBundleHash))?
+                        .interop_message
+                        .clone(),
+                )
+            } else {
+                None
+            };
+            let paymaster_input =
+                paymaster::compose_paymaster_input(interop_tx, fee_message.as_ref());
+            let paymaster_token = if interop_tx.destinationPaymaster
+                == destination_interop_chain.get_preferred_paymaster().await?
+            {
+                Some(
+                    destination_interop_chain
+                        .get_paymaster_basic_token()
+                        .await?,
+                )
+            } else {
+                None
+            };
+            let flow =
+                paymaster::validate_paymaster_input(&paymaster_input, interop_tx, paymaster_token)?;
+            println!("  Paymaster flow: {:?}", flow);
+            paymaster_input
+        };
+
+        // Same as the chain that emitted the message (checked in from_log).
+        let source_chain_id = self.chain_id;
+        let account = accounts
+            .get(
+                destination_interop_chain,
+                source_chain_id,
                 interop_tx.sourceChainSender,
             )
-            .await;
+            .await?;
+        let from_addr = account.address;
 
         println!("  'from' address set to: {:?}", from_addr);
 
-        let code = destination_interop_chain
-            .provider
-            .get_code_at(from_addr)
-            .await
-            .unwrap();
-        if code.len() == 0 {
-            // No contract deployed.
+        if !account.deployed {
             println!("  No account for this user - deploying aliased account.");
 
-            let admin_provider = zksync_provider()
-                .with_recommended_fillers()
-                .wallet(destination_interop_chain.admin_wallet.clone())
-                .on_http(destination_interop_chain.rpc.parse().unwrap());
-
-            let contract =
-                InteropCenter::new(destination_interop_chain.interop_address, &admin_provider);
-
             // TODO: before sending, maybe check if the message was forwarded already..
-
-            let tx_hash = contract
-                .deployAliasedAccount(
+            let deployment = accounts
+                .ensure_deployed(
+                    destination_interop_chain,
+                    source_chain_id,
                     interop_tx.sourceChainSender,
-                    self.interop_message.sourceChainId,
                 )
-                .send()
-                .await
-                .unwrap()
-                .watch()
-                .await
-                .unwrap();
-
-            println!(
-                "   Deployed aliased account on chain {} {:?} with tx {:?}",
-                destination_chain_id, from_addr, tx_hash
-            );
+                .await?;
+            if let Some(deployment) = deployment {
+                fee_ledger
+                    .record(FeeRecord {
+                        timestamp: fees::now(),
+                        msg_hash: self.msg_hash,
+                        source_chain: self.chain_id,
+                        destination_chain: destination_interop_chain.chain_id,
+                        kind: FeeKind::AliasedAccountDeployment,
+                        tx_hash: deployment.tx_hash,
+                        cost: deployment.cost,
+                        collected: U256::ZERO,
+                        base_token_price: destination_interop_chain.base_token_price(),
+                    })
+                    .await;
+            }
         }
 
         let map = all_messages.lock().await;
 
-        let paymaster_input = if !interop_tx.feesBundleHash.is_zero() {
-            println!("  Fee Bundle is present");
-            let fee_interop_msg = map
-                .get(&interop_tx.feesBundleHash)
-                .expect(&format!(
-                    "Failed to find fee bundle msg: {:?}",
-                    interop_tx.feesBundleHash
-                ))
-                .interop_message
-                .clone();
-            InteropMessage::abi_encode(&fee_interop_msg)
-        } else {
-            vec![]
-        };
-
-        let paymaster_params = PaymasterParams {
-            paymaster: interop_tx.destinationPaymaster,
-            paymaster_input: paymaster_input.into(),
-        };
-        let paymaster = paymaster_params.paymaster;
-        println!("  Using paymaster: {}", paymaster);
-
-        destination_interop_chain.refill_paymaster(paymaster).await;
-
-        let bundle_msg = map.get(&interop_tx.bundleHash).unwrap();
+        let bundle_msg = map
+            .get(&interop_tx.bundleHash)
+            .ok_or(RevertReason::MissingBundle(interop_tx.bundleHash))?;
 
         let proof = Bytes::new();
 
@@ -309,23 +484,33 @@ impl InteropMessageParsed {
             feesBundleHash: interop_tx.feesBundleHash,
         };
 
-        let custom_signature = InteropCenter::TransactionReservedStuff::abi_encode(&stuff).into();
+        // Proof of the message that carried this transaction (empty while it is forwarded by the relayer).
+        let custom_signature = accounts::authorization_signature(stuff, Bytes::new());
 
-        let tx = TransactionRequest::default()
+        let mut tx = TransactionRequest::default()
             .with_call(&calldata)
             .with_to(destination_interop_chain.interop_address)
             // FIXME: no value passing.
             //.with_value(interop_tx.value)
-            .with_gas_limit(interop_tx.gasLimit.try_into().unwrap())
-            // Constant for now.
-            .with_gas_per_pubdata(U256::from(50_000))
-            .with_max_fee_per_gas(interop_tx.gasPrice.try_into().unwrap())
-            .with_max_priority_fee_per_gas(interop_tx.gasPrice.try_into().unwrap())
+            // Out of range values can't pass the admission caps anyway.
+            .with_gas_limit(interop_tx.gasLimit.saturating_to())
+            .with_gas_per_pubdata(U256::from(INTEROP_GAS_PER_PUBDATA))
+            .with_max_fee_per_gas(interop_tx.gasPrice.saturating_to())
+            .with_max_priority_fee_per_gas(interop_tx.gasPrice.saturating_to())
             .with_from(from_addr)
-            .with_paymaster(paymaster_params)
             .with_custom_signature(custom_signature);
 
-        Some((destination_chain_id, tx))
+        if interop_tx.destinationPaymaster.is_zero() {
+            println!("  No paymaster - paid by the aliased account");
+        } else {
+            println!("  Using paymaster: {}", interop_tx.destinationPaymaster);
+            tx = tx.with_paymaster(PaymasterParams {
+                paymaster: interop_tx.destinationPaymaster,
+                paymaster_input: paymaster_input.into(),
+            });
+        }
+
+        Ok(Some(tx))
     }
 
     // Checks if the interop message is of type b.
@@ -333,216 +518,904 @@ impl InteropMessageParsed {
 
 #[derive(Clone)]
 pub struct InteropChain {
-    pub provider: alloy::providers::fillers::FillProvider<
-        alloy::providers::fillers::JoinFill<
-            alloy::providers::Identity,
-            alloy::providers::fillers::JoinFill<
-                alloy_zksync::provider::fillers::Eip712FeeFiller,
-                alloy::providers::fillers::JoinFill<
-                    alloy::providers::fillers::NonceFiller,
-                    alloy::providers::fillers::ChainIdFiller,
-                >,
-            >,
-        >,
-        alloy::providers::RootProvider<
-            alloy::transports::http::Http<reqwest::Client>,
-            alloy_zksync::network::Zksync,
-        >,
-        alloy::transports::http::Http<reqwest::Client>,
-        alloy_zksync::network::Zksync,
-    >,
+    pub endpoints: Arc<Endpoints>,
     pub interop_address: Address,
-    pub rpc: String,
     pub chain_id: u64,
     pub admin_wallet: ZksyncWallet,
-    pub base_token_price: u64,
-    pub tokens_for_paymaster: U256,
+    // Price of the base token (10^18) in cents - updated by the price source.
+    pub base_token_price: Arc<AtomicU64>,
+    // How often to poll for new logs (when not streaming).
+    pub poll_interval: Duration,
+    // How many blocks behind the head a log must be, before we process it (when not streaming).
+    pub confirmations: u64,
+    // All the logs up to (and including) this block were already processed.
+    pub last_processed_block: Arc<AtomicU64>,
+    pub listener_status: Arc<std::sync::Mutex<ListenerStatus>>,
+    // When set, listener stops processing new logs (until resumed).
+    pub paused: Arc<AtomicBool>,
+    // Address of the admin_wallet.
+    pub admin_address: Address,
+    // Set when the chain was removed from the running relayer - its listener stops.
+    pub removed: Arc<AtomicBool>,
+}
+
+// Settings shared by all the chains - also used for the chains that are added at runtime.
+pub struct ChainSettings {
+    pub admin_wallet: ZksyncWallet,
+    pub admin_address: Address,
+    pub quorum: usize,
+    // Used for the chains added at runtime (they don't have per-chain values on the command line).
+    pub poll_interval: Duration,
+    pub confirmations: u64,
+}
+
+// Chains that the relayer is currently working with - can change at runtime (admin API).
+pub type SharedChains = Arc<RwLock<HashMap<u64, Arc<InteropChain>>>>;
+
+// Type C transactions must use this gas per pubdata limit (checked by InteropCenter.verifyPotentialTransaction).
+const INTEROP_GAS_PER_PUBDATA: u64 = 50_000;
+
+// Fee parameters that the chain currently requires for a transaction (zks_estimateFee).
+#[derive(Debug, Deserialize)]
+pub struct FeeEstimate {
+    pub gas_limit: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_per_pubdata_limit: U256,
+}
+
+impl FeeEstimate {
+    // Returns why the interop transaction can't be executed with the current fees (if it can't).
+    // Gas limit and price come from the user (and are part of the message), so the relayer can't raise them.
+    pub fn underpriced_reason(
+        &self,
+        interop_tx: &InteropCenter::InteropTransaction,
+    ) -> Option<String> {
+        if interop_tx.gasLimit < self.gas_limit {
+            Some(format!(
+                "gas limit {} is below the estimated {}",
+                interop_tx.gasLimit, self.gas_limit
+            ))
+        } else if interop_tx.gasPrice < self.max_fee_per_gas {
+            Some(format!(
+                "gas price {} is below the current {}",
+                interop_tx.gasPrice, self.max_fee_per_gas
+            ))
+        } else if self.gas_per_pubdata_limit > U256::from(INTEROP_GAS_PER_PUBDATA) {
+            Some(format!(
+                "chain requires {} gas per pubdata, interop transactions use {}",
+                self.gas_per_pubdata_limit, INTEROP_GAS_PER_PUBDATA
+            ))
+        } else {
+            None
+        }
+    }
 }
 
 const BLOCKS_IN_THE_PAST: u64 = 1000;
+// Messages, whose simulation failed with a temporary error, are retried after this delay (at most MAX_ATTEMPTS times).
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: u32 = 6;
+// How many blocks (behind the last processed one) we keep checking for reorgs.
+const REORG_TRACKING_DEPTH: u64 = 64;
+// Longest pause between the retries, when the listener can't read from any of the chain endpoints.
+const MAX_LISTENER_BACKOFF: Duration = Duration::from_secs(60);
 
 impl InteropChain {
+    // Connects to the chain endpoints (comma separated in 'rpc') and checks that they all point to the same chain.
+    pub async fn connect(
+        rpc: &str,
+        interop_address: Address,
+        base_token_price: u64,
+        poll_interval: Duration,
+        confirmations: u64,
+        settings: &ChainSettings,
+    ) -> anyhow::Result<InteropChain> {
+        let urls: Vec<String> = rpc.split(',').map(|url| url.trim().to_string()).collect();
+        let endpoints = Arc::new(Endpoints::new(&urls, settings.quorum)?);
+
+        // All the endpoints must point to the same chain. The ones that don't answer are skipped
+        // (and marked as unhealthy) - health checks bring them back once they recover.
+        let mut chain_ids = HashSet::new();
+        for endpoint in &endpoints.endpoints {
+            match endpoint.provider.get_chain_id().await {
+                Ok(chain_id) => {
+                    chain_ids.insert(chain_id);
+                }
+                Err(error) => endpoint.record_failure(&error.to_string()),
+            }
+        }
+        anyhow::ensure!(
+            !chain_ids.is_empty(),
+            "None of the endpoints {:?} is reachable",
+            urls
+        );
+        anyhow::ensure!(
+            chain_ids.len() == 1,
+            "Endpoints {:?} point to different chains: {:?}",
+            urls,
+            chain_ids
+        );
+        let chain_id = chain_ids.into_iter().next().unwrap();
+
+        println!(
+            "Interop on chain {} ({} endpoints). base token price: {} cents",
+            chain_id,
+            urls.len(),
+            base_token_price
+        );
+
+        Ok(InteropChain {
+            endpoints,
+            interop_address,
+            chain_id,
+            admin_wallet: settings.admin_wallet.clone(),
+            base_token_price: Arc::new(AtomicU64::new(base_token_price)),
+            poll_interval,
+            confirmations,
+            last_processed_block: Arc::new(AtomicU64::new(0)),
+            listener_status: Arc::new(std::sync::Mutex::new(ListenerStatus::Starting)),
+            paused: Arc::new(AtomicBool::new(false)),
+            admin_address: settings.admin_address,
+            removed: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    // Provider for the currently preferred (healthy, fastest) endpoint.
+    pub fn provider(&self) -> &ChainProvider {
+        &self.endpoints.active().provider
+    }
+
+    pub fn rpc(&self) -> &str {
+        &self.endpoints.active().url
+    }
+
     pub async fn get_aliased_account_address(
         &self,
         source_chain: U256,
         source_address: Address,
-    ) -> Address {
-        let contract = InteropCenter::new(self.interop_address, &self.provider);
-        contract
-            .getAliasedAccount(source_address, source_chain)
-            .call()
+    ) -> Result<Address, EndpointError> {
+        self.endpoints
+            .quorum_call(|provider| async move {
+                let contract = InteropCenter::new(self.interop_address, &provider);
+                contract
+                    .getAliasedAccount(source_address, source_chain)
+                    .call()
+                    .await
+                    .map(|result| result._0)
+            })
             .await
-            .unwrap()
-            ._0
     }
 
-    pub async fn get_preferred_paymaster(&self) -> Address {
-        let contract = InteropCenter::new(self.interop_address, &self.provider);
-        contract
-            .preferredPaymasters(self.chain_id.try_into().unwrap())
-            .call()
+    pub async fn get_aliased_account_bytecode_hash(&self) -> Result<B256, EndpointError> {
+        self.endpoints
+            .quorum_call(|provider| async move {
+                let contract = InteropCenter::new(self.interop_address, &provider);
+                contract
+                    .aliasedAccountBytecodeHash()
+                    .call()
+                    .await
+                    .map(|result| result._0)
+            })
             .await
-            .unwrap()
-            ._0
     }
 
-    pub async fn get_paymaster_basic_token(&self) -> Address {
-        let paymaster = self.get_preferred_paymaster().await;
-        let contract = CrossPaymaster::new(paymaster, &self.provider);
-        contract
-            .paymasterTokenAddress()
-            .call()
+    pub async fn get_preferred_paymaster(&self) -> Result<Address, EndpointError> {
+        self.endpoints
+            .call(|provider| async move {
+                let contract = InteropCenter::new(self.interop_address, &provider);
+                contract
+                    .preferredPaymasters(self.chain_id.try_into().unwrap())
+                    .call()
+                    .await
+                    .map(|result| result._0)
+            })
+            .await
+    }
+
+    pub async fn get_paymaster_basic_token(&self) -> Result<Address, EndpointError> {
+        let paymaster = self.get_preferred_paymaster().await?;
+        self.endpoints
+            .call(|provider| async move {
+                let contract = CrossPaymaster::new(paymaster, &provider);
+                contract
+                    .paymasterTokenAddress()
+                    .call()
+                    .await
+                    .map(|result| result.paymasterTokenAddress)
+            })
             .await
-            .unwrap()
-            .paymasterTokenAddress
     }
 
-    pub async fn is_bundle_executed(&self, bundle_hash: FixedBytes<32>) -> bool {
-        let contract = InteropCenter::new(self.interop_address, &self.provider);
-        contract
-            .executedBundles(bundle_hash)
-            .call()
+    pub async fn is_bundle_executed(
+        &self,
+        bundle_hash: FixedBytes<32>,
+    ) -> Result<bool, EndpointError> {
+        self.endpoints
+            .quorum_call(|provider| async move {
+                let contract = InteropCenter::new(self.interop_address, &provider);
+                contract
+                    .executedBundles(bundle_hash)
+                    .call()
+                    .await
+                    .map(|result| result._0)
+            })
             .await
-            .unwrap()
-            ._0
-    }
-
-    pub async fn refill_paymaster(&self, paymaster: Address) {
-        let balance = self.provider.get_balance(paymaster).await.unwrap();
-
-        // We want 0.02 Eth (roughly).
-        let limit = self.tokens_for_paymaster;
-        if balance < limit {
-            let admin_provider = zksync_provider()
-                .with_recommended_fillers()
-                .wallet(self.admin_wallet.clone())
-                .on_http(self.rpc.parse().unwrap());
-            let tx = TransactionRequest::default()
-                .with_to(paymaster)
-                .with_value(limit);
-            let tx_hash = admin_provider
-                .send_transaction(tx)
-                .await
-                .unwrap()
-                .watch()
+    }
+
+    pub async fn is_message_received(
+        &self,
+        msg_hash: FixedBytes<32>,
+    ) -> Result<bool, EndpointError> {
+        self.endpoints
+            .quorum_call(|provider| async move {
+                let contract = InteropCenter::new(self.interop_address, &provider);
+                contract
+                    .receivedMessages(msg_hash)
+                    .call()
+                    .await
+                    .map(|result| result._0)
+            })
+            .await
+    }
+
+    pub async fn has_code(&self, address: Address) -> Result<bool, EndpointError> {
+        self.endpoints
+            .call(|provider| async move { provider.get_code_at(address).await })
+            .await
+            .map(|code| !code.is_empty())
+    }
+
+    pub async fn get_balance(&self, address: Address) -> Result<U256, EndpointError> {
+        self.endpoints
+            .call(|provider| async move { provider.get_balance(address).await })
+            .await
+    }
+
+    pub async fn get_block_number(&self) -> Result<u64, EndpointError> {
+        self.endpoints
+            .call(|provider| async move { provider.get_block_number().await })
+            .await
+    }
+
+    pub async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, EndpointError> {
+        self.endpoints
+            .call(|provider| async move { provider.get_logs(filter).await })
+            .await
+    }
+
+    // Runs the request with failover on the transport errors. An error response (e.g. a revert)
+    // comes from a working node - it is returned as the reason, without asking the other endpoints.
+    pub async fn call_or_revert<T, F, Fut>(&self, f: F) -> Result<T, RevertReason>
+    where
+        F: Fn(ChainProvider) -> Fut,
+        Fut: futures::future::Future<Output = Result<T, RpcError<TransportErrorKind>>>,
+    {
+        self.endpoints
+            .call(|provider| {
+                let request = f(provider);
+                async move {
+                    match request.await {
+                        Ok(result) => Ok(Ok(result)),
+                        Err(error) if error.as_error_resp().is_some() => {
+                            Ok(Err(RevertReason::from_rpc_error(&error)))
+                        }
+                        Err(error) => Err(error),
+                    }
+                }
+            })
+            .await?
+    }
+
+    // Simulates the transaction (eth_call).
+    pub async fn simulate(&self, tx: &TransactionRequest) -> Result<Bytes, RevertReason> {
+        self.call_or_revert(|provider| async move { provider.call(tx).await })
+            .await
+    }
+
+    pub async fn estimate_fee(&self, tx: &TransactionRequest) -> Result<FeeEstimate, RevertReason> {
+        self.call_or_revert(|provider| async move {
+            provider
+                .raw_request::<_, FeeEstimate>("zks_estimateFee".into(), (tx.clone(),))
                 .await
-                .unwrap();
-            println!("    Sending {} tokens to paymaster : {:?} ", limit, tx_hash);
+        })
+        .await
+    }
+
+    pub fn base_token_price(&self) -> u64 {
+        self.base_token_price.load(Ordering::Relaxed)
+    }
+
+    // Amount of the base tokens worth the given number of cents.
+    pub fn cents_to_tokens(&self, cents: u64) -> U256 {
+        let base_token: U256 = 1_000_000_000_000_000_000u64.try_into().unwrap();
+        base_token
+            .checked_mul(U256::from(cents))
+            .unwrap()
+            .checked_div(U256::from(self.base_token_price()))
+            .unwrap()
+    }
+
+    pub fn interop_logs_filter(&self) -> Filter {
+        Filter::new()
+            .event_signature(InteropCenter::InteropMessageSent::SIGNATURE_HASH)
+            .address(self.interop_address)
+    }
+
+    fn set_listener_status(&self, status: ListenerStatus) {
+        *self.listener_status.lock().unwrap() = status;
+    }
+
+    // Waits while the listener is paused (from the admin API).
+    async fn wait_while_paused(&self, shutdown: &mut watch::Receiver<bool>) {
+        if !self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        println!("Listener on chain {} paused", self.chain_id);
+        self.set_listener_status(ListenerStatus::Paused);
+        while self.paused.load(Ordering::Relaxed)
+            && !*shutdown.borrow()
+            && !self.removed.load(Ordering::Relaxed)
+        {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {},
+                _ = shutdown.changed() => {},
+            }
+        }
+        println!("Listener on chain {} resumed", self.chain_id);
+        self.set_listener_status(ListenerStatus::Running);
+    }
+
+    // Repeats the read until it succeeds, backing off (up to MAX_LISTENER_BACKOFF) while all the endpoints fail.
+    // Returns None when the shutdown is requested or the chain is removed.
+    async fn retry_with_backoff<T, F, Fut>(
+        &self,
+        what: &str,
+        shutdown: &mut watch::Receiver<bool>,
+        f: F,
+    ) -> Option<T>
+    where
+        F: Fn() -> Fut,
+        Fut: futures::future::Future<Output = Result<T, EndpointError>>,
+    {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            match f().await {
+                Ok(value) => return Some(value),
+                Err(err) => println!(
+                    "!! Failed to get {} on chain {}: {} - retrying in {}s",
+                    what,
+                    self.chain_id,
+                    err,
+                    backoff.as_secs()
+                ),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {},
+                _ = shutdown.changed() => {},
+            }
+            if *shutdown.borrow() || self.removed.load(Ordering::Relaxed) {
+                return None;
+            }
+            backoff = (backoff * 2).min(MAX_LISTENER_BACKOFF);
         }
     }
 
     // streaming has lower latency, but works only on 'local' chains.
     // for external ones you have to actively pull.
-    pub async fn listen_on_interop_messages<F, Fut>(&self, streaming: bool, callback: F)
-    where
+    // Returns when the shutdown is requested or the chain is removed (after the callback in progress finishes).
+    pub async fn listen_on_interop_messages<F, Fut>(
+        &self,
+        streaming: bool,
+        resume_from: Option<u64>,
+        shutdown: watch::Receiver<bool>,
+        callback: F,
+    ) where
         F: Fn(Log) -> Fut,
         Fut: futures::future::Future<Output = ()>,
     {
-        let latest_block = self.provider.get_block_number().await.unwrap();
+        self.set_listener_status(ListenerStatus::CatchingUp);
+        self.watch_interop_messages(streaming, resume_from, shutdown, callback)
+            .await;
+        self.set_listener_status(ListenerStatus::Stopped);
+    }
 
-        // Look at last 1k blocks.
-        let filter = Filter::new()
-            .from_block(latest_block.saturating_sub(BLOCKS_IN_THE_PAST))
-            .event_signature(InteropCenter::InteropMessageSent::SIGNATURE_HASH)
-            .address(self.interop_address);
+    async fn watch_interop_messages<F, Fut>(
+        &self,
+        streaming: bool,
+        resume_from: Option<u64>,
+        mut shutdown: watch::Receiver<bool>,
+        callback: F,
+    ) where
+        F: Fn(Log) -> Fut,
+        Fut: futures::future::Future<Output = ()>,
+    {
+        let stopped = |shutdown: &watch::Receiver<bool>| {
+            *shutdown.borrow() || self.removed.load(Ordering::Relaxed)
+        };
+
+        let Some(latest_block) = self
+            .retry_with_backoff("block number", &mut shutdown, || self.get_block_number())
+            .await
+        else {
+            return;
+        };
+        let safe_block = latest_block.saturating_sub(self.confirmations);
 
-        let logs = self.provider.get_logs(&filter).await.unwrap();
+        let events_filter = self.interop_logs_filter();
 
-        for log in logs {
-            callback(log).await;
+        // Continue after the checkpoint, or look at last 1k blocks.
+        let from_block = match resume_from {
+            Some(checkpoint) => {
+                println!(
+                    "Resuming chain {} from checkpoint {}",
+                    self.chain_id, checkpoint
+                );
+                checkpoint + 1
+            }
+            None => safe_block.saturating_sub(BLOCKS_IN_THE_PAST),
+        };
+        let logs = if from_block <= safe_block {
+            let filter = events_filter
+                .clone()
+                .from_block(from_block)
+                .to_block(safe_block);
+            let Some(logs) = self
+                .retry_with_backoff("logs", &mut shutdown, || self.get_logs(&filter))
+                .await
+            else {
+                return;
+            };
+            logs
+        } else {
+            vec![]
+        };
+
+        for log in &logs {
+            if stopped(&shutdown) {
+                return;
+            }
+            callback(log.clone()).await;
         }
+        let mut latest_processed_block = safe_block.max(from_block.saturating_sub(1));
+        self.last_processed_block
+            .store(latest_processed_block, Ordering::Relaxed);
+        self.set_listener_status(ListenerStatus::Running);
 
         if streaming {
             println!("Starting to watch logs...");
 
-            let mut log_stream = self
-                .provider
-                .watch_logs(&filter)
+            // Log filter is installed on the preferred endpoint (failing over to the other ones).
+            let filter = &events_filter;
+            let subscribe = || {
+                self.endpoints.call(|provider| async move {
+                    provider
+                        .watch_logs(filter)
+                        .await
+                        .map(|poller| poller.into_stream())
+                })
+            };
+            let Some(mut log_stream) = self
+                .retry_with_backoff("log filter", &mut shutdown, subscribe)
                 .await
-                .unwrap()
-                .into_stream();
+            else {
+                return;
+            };
+            let mut stream_url = self.rpc().to_string();
 
-            while let Some(log) = log_stream.next().await {
-                for l in log {
+            loop {
+                let logs = tokio::select! {
+                    logs = log_stream.next() => logs,
+                    _ = shutdown.changed() => return,
+                    // Wake up from time to time, to notice that the chain was removed
+                    // or that the endpoint we watch on is no longer the preferred one.
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                        if stopped(&shutdown) {
+                            return;
+                        }
+                        if self.rpc() == stream_url {
+                            continue;
+                        }
+                        None
+                    }
+                };
+                let Some(logs) = logs else {
+                    // Stream ended, or the endpoint failed - watch on the preferred endpoint again,
+                    // and fetch the logs that were emitted in the meantime.
+                    println!(
+                        "!! Log stream from {} on chain {} stopped - watching again",
+                        stream_url, self.chain_id
+                    );
+                    let Some(new_stream) = self
+                        .retry_with_backoff("log filter", &mut shutdown, subscribe)
+                        .await
+                    else {
+                        return;
+                    };
+                    log_stream = new_stream;
+                    stream_url = self.rpc().to_string();
+
+                    let Some(latest_block) = self
+                        .retry_with_backoff("block number", &mut shutdown, || {
+                            self.get_block_number()
+                        })
+                        .await
+                    else {
+                        return;
+                    };
+                    let from_block = self.last_processed_block.load(Ordering::Relaxed) + 1;
+                    if from_block <= latest_block {
+                        let filter = events_filter
+                            .clone()
+                            .from_block(from_block)
+                            .to_block(latest_block);
+                        let Some(missed_logs) = self
+                            .retry_with_backoff("logs", &mut shutdown, || self.get_logs(&filter))
+                            .await
+                        else {
+                            return;
+                        };
+                        // Handlers check the on-chain state, so the logs that come again are safe.
+                        for l in missed_logs {
+                            if stopped(&shutdown) {
+                                return;
+                            }
+                            callback(l).await;
+                        }
+                        self.last_processed_block
+                            .fetch_max(latest_block.saturating_sub(1), Ordering::Relaxed);
+                    }
+                    continue;
+                };
+                self.wait_while_paused(&mut shutdown).await;
+                for l in logs {
+                    if stopped(&shutdown) {
+                        return;
+                    }
+                    // Other logs from the same block might still come - so only the previous block is done.
+                    let block = l.block_number.unwrap_or_default().saturating_sub(1);
                     callback(l).await;
+                    self.last_processed_block
+                        .fetch_max(block, Ordering::Relaxed);
                 }
             }
         } else {
-            println!("Using 30 seconds polling to watch logs..");
-            let mut latest_processed_block = latest_block;
+            println!(
+                "Using {} seconds polling ({} confirmations) to watch logs on chain {}..",
+                self.poll_interval.as_secs(),
+                self.confirmations,
+                self.chain_id
+            );
+            // Logs that we already passed to the callback, grouped by block number.
+            // Used to detect reorgs within the last REORG_TRACKING_DEPTH blocks.
+            let mut processed_logs = group_logs_by_block(logs);
             loop {
-                tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
-                let latest_block = self.provider.get_block_number().await.unwrap();
-                if latest_block > latest_processed_block {
-                    let filter = Filter::new()
-                        .from_block(latest_processed_block)
-                        .to_block(latest_block)
-                        .event_signature(InteropCenter::InteropMessageSent::SIGNATURE_HASH)
-                        .address(self.interop_address);
-                    let logs = self.provider.get_logs(&filter).await.unwrap();
-
-                    for log in logs {
-                        callback(log).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(self.poll_interval) => {},
+                    _ = shutdown.changed() => return,
+                }
+                self.wait_while_paused(&mut shutdown).await;
+                if stopped(&shutdown) {
+                    return;
+                }
+                let Some(latest_block) = self
+                    .retry_with_backoff("block number", &mut shutdown, || self.get_block_number())
+                    .await
+                else {
+                    return;
+                };
+                let safe_block = latest_block.saturating_sub(self.confirmations);
+
+                if let Some((&first_tracked_block, _)) = processed_logs.first_key_value() {
+                    let filter = events_filter
+                        .clone()
+                        .from_block(first_tracked_block)
+                        .to_block(latest_processed_block);
+                    let Some(current_logs) = self
+                        .retry_with_backoff("logs", &mut shutdown, || self.get_logs(&filter))
+                        .await
+                    else {
+                        return;
+                    };
+                    let current_logs = group_logs_by_block(current_logs);
+
+                    if let Some(reorg_block) = find_reorg(&processed_logs, &current_logs) {
+                        println!(
+                            "!! Reorg detected on chain {} at block {} - reprocessing up to {}",
+                            self.chain_id, reorg_block, latest_processed_block
+                        );
+                        let stale_logs = processed_logs.split_off(&reorg_block);
+                        let current_logs = current_logs
+                            .into_iter()
+                            .filter(|(block, _)| *block >= reorg_block)
+                            .collect::<BTreeMap<_, _>>();
+
+                        let current_hashes: HashSet<B256> = current_logs
+                            .values()
+                            .flatten()
+                            .map(|log| log.topics()[1])
+                            .collect();
+
+                        // Messages that are no longer present on the canonical chain.
+                        for mut log in stale_logs.into_values().flatten() {
+                            if stopped(&shutdown) {
+                                return;
+                            }
+                            if !current_hashes.contains(&log.topics()[1]) {
+                                log.removed = true;
+                                callback(log).await;
+                            }
+                        }
+                        // Messages that were re-included (or newly included) in the new blocks.
+                        // Handlers check the on-chain state, so re-processing is safe.
+                        for log in current_logs.values().flatten() {
+                            if stopped(&shutdown) {
+                                return;
+                            }
+                            callback(log.clone()).await;
+                        }
+                        processed_logs.extend(current_logs);
+                    }
+                }
+
+                if safe_block > latest_processed_block {
+                    // Range is inclusive on both ends - so start right after the last processed block.
+                    let filter = events_filter
+                        .clone()
+                        .from_block(latest_processed_block + 1)
+                        .to_block(safe_block);
+                    let Some(logs) = self
+                        .retry_with_backoff("logs", &mut shutdown, || self.get_logs(&filter))
+                        .await
+                    else {
+                        return;
+                    };
+
+                    for log in &logs {
+                        if stopped(&shutdown) {
+                            return;
+                        }
+                        callback(log.clone()).await;
                     }
+                    processed_logs.extend(group_logs_by_block(logs));
+                    latest_processed_block = safe_block;
+                    self.last_processed_block
+                        .store(latest_processed_block, Ordering::Relaxed);
                 }
-                latest_processed_block = latest_block;
+
+                // Forget about blocks that are too deep to be reorged.
+                processed_logs = processed_logs
+                    .split_off(&latest_processed_block.saturating_sub(REORG_TRACKING_DEPTH));
             }
         }
     }
 }
 
+fn group_logs_by_block(logs: Vec<Log>) -> BTreeMap<u64, Vec<Log>> {
+    let mut result: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
+    for log in logs {
+        result
+            .entry(log.block_number.unwrap_or_default())
+            .or_default()
+            .push(log);
+    }
+    result
+}
+
+// Returns the first block, where the logs that we processed (together with their block hashes)
+// differ from the ones currently on chain.
+fn find_reorg(
+    processed: &BTreeMap<u64, Vec<Log>>,
+    current: &BTreeMap<u64, Vec<Log>>,
+) -> Option<u64> {
+    let fingerprint = |logs: Option<&Vec<Log>>| -> Vec<(Option<B256>, B256)> {
+        logs.map(|logs| {
+            logs.iter()
+                .map(|log| (log.block_hash, log.topics()[1]))
+                .collect()
+        })
+        .unwrap_or_default()
+    };
+    processed
+        .keys()
+        .chain(current.keys())
+        .copied()
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .find(|block| fingerprint(processed.get(block)) != fingerprint(current.get(block)))
+}
+
 async fn handle_type_a_message(
     msg: &InteropMessageParsed,
     providers_map: &HashMap<u64, Arc<InteropChain>>,
-) {
-    // Forward the message to all the other chains.
+    policy: &TrustPolicy,
+    in_flight: &Arc<InFlight>,
+    fee_ledger: &FeeLedger,
+) -> Result<(), MessageStatus> {
+    // Forward the message to all the chains that accept messages from the source chain.
+    let mut failed = vec![];
+    // Chains where we couldn't check whether the message was forwarded already.
+    let mut unknown = vec![];
     for (chain_id, entry) in providers_map {
+        if !policy.allows(msg.chain_id, *chain_id) {
+            continue;
+        }
+        let _in_flight = in_flight.start(format!(
+            "forward of {:?} from chain {} to chain {}",
+            msg.msg_hash, msg.chain_id, chain_id
+        ));
         let admin_provider = zksync_provider()
             .with_recommended_fillers()
             .wallet(entry.admin_wallet.clone())
-            .on_http(entry.rpc.parse().unwrap());
+            .on_http(entry.rpc().parse().unwrap());
 
         let contract = InteropCenter::new(entry.interop_address, &admin_provider);
 
-        if !contract
-            .receivedMessages(msg.msg_hash)
-            .call()
-            .await
-            .unwrap()
-            ._0
-        {
-            let tx_hash = contract
-                .receiveInteropMessage(msg.msg_hash)
-                .send()
-                .await
-                .unwrap()
-                .watch()
-                .await
-                .unwrap();
-
-            println!("  Forwarded msg to {} with tx {:?}", chain_id, tx_hash);
+        let received = match entry.is_message_received(msg.msg_hash).await {
+            Ok(received) => received,
+            Err(error) => {
+                println!(
+                    "!! Can't check msg {:?} on chain {}: {}",
+                    msg.msg_hash, chain_id, error
+                );
+                unknown.push(format!("chain {}: {}", chain_id, error));
+                continue;
+            }
+        };
+        if !received {
+            let pending = match contract.receiveInteropMessage(msg.msg_hash).send().await {
+                Ok(pending) => pending,
+                Err(error) => {
+                    let reason = RevertReason::from_contract_error(&error).describe();
+                    println!(
+                        "!! Forwarding msg {:?} to {} failed: {}",
+                        msg.msg_hash, chain_id, reason
+                    );
+                    failed.push(format!("chain {}: {}", chain_id, reason));
+                    continue;
+                }
+            };
+            match pending.get_receipt().await {
+                Ok(receipt) => {
+                    println!(
+                        "  Forwarded msg to {} with tx {:?}",
+                        chain_id,
+                        receipt.transaction_hash()
+                    );
+                    fee_ledger
+                        .record(FeeRecord {
+                            timestamp: fees::now(),
+                            msg_hash: msg.msg_hash,
+                            source_chain: msg.chain_id,
+                            destination_chain: *chain_id,
+                            kind: FeeKind::Forward,
+                            tx_hash: receipt.transaction_hash(),
+                            cost: receipt_cost(&receipt),
+                            collected: U256::ZERO,
+                            base_token_price: entry.base_token_price(),
+                        })
+                        .await;
+                }
+                Err(error) => {
+                    println!(
+                        "!! Forwarding msg {:?} to {} failed: {}",
+                        msg.msg_hash, chain_id, error
+                    );
+                    failed.push(format!("chain {}: {}", chain_id, error));
+                }
+            }
         }
     }
+    // Reads that failed are retried automatically (together with any failed forwards).
+    if !unknown.is_empty() {
+        unknown.extend(failed);
+        unknown.sort();
+        Err(MessageStatus::Retrying(format!(
+            "forwarding state unknown on {}",
+            unknown.join("; ")
+        )))
+    } else if !failed.is_empty() {
+        failed.sort();
+        Err(MessageStatus::Failed(format!(
+            "forwarding failed on {}",
+            failed.join("; ")
+        )))
+    } else {
+        Ok(())
+    }
 }
 
 async fn handle_type_c_message(
     msg: &InteropMessageParsed,
-    providers_map: &HashMap<u64, Arc<InteropChain>>,
+    interop_tx: &InteropCenter::InteropTransaction,
+    destination: &InteropChain,
     shared_map: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>>,
-) {
-    let transaction_request = msg
-        .create_transaction_request(providers_map, shared_map.clone())
-        .await;
-
-    if let Some((destination_chain, mut tx)) = transaction_request {
-        // We do a lot of work here, as era doesn't accept 'eth_sendTransaction' and alloy really wants
-        // to sign it with some wallet.
-        // So we construct the transaction parts manually - and then send as 'raw' transaction.
-
-        tx.prep_for_submission();
-        let provider = &providers_map.get(&destination_chain).unwrap().provider;
+    in_flight: &Arc<InFlight>,
+    accounts: &AccountManager,
+    fee_ledger: &FeeLedger,
+    admission: &Admission,
+) -> MessageStatus {
+    let _in_flight = in_flight.start(format!(
+        "type C transaction {:?} from chain {}",
+        msg.msg_hash, msg.chain_id
+    ));
+    let transaction_request = match msg
+        .create_transaction_request(
+            interop_tx,
+            destination,
+            shared_map.clone(),
+            accounts,
+            fee_ledger,
+        )
+        .await
+    {
+        Ok(transaction_request) => transaction_request,
+        Err(reason) if reason.action() == RevertAction::RetryLater => {
+            println!(
+                "  Preparing transaction for msg {:?} failed: {} - will retry",
+                msg.msg_hash,
+                reason.describe()
+            );
+            return MessageStatus::Retrying(reason.describe());
+        }
+        Err(reason) => {
+            println!(
+                "!! Preparing transaction for msg {:?} failed: {}",
+                msg.msg_hash,
+                reason.describe()
+            );
+            return MessageStatus::Failed(format!(
+                "transaction preparation failed: {}",
+                reason.describe()
+            ));
+        }
+    };
 
-        let sendable_tx = provider.fill(tx).await.unwrap();
+    if let Some(mut tx) = transaction_request {
+        let destination_chain = destination.chain_id;
+
+        // Simulate first - eth_call covers the execution (bundle), and fee estimation also runs
+        // the account and paymaster validation.
+        if let Err(reason) = destination.simulate(&tx).await {
+            return simulation_failed(msg, reason);
+        }
+        // Don't submit transactions that can't pass with the current fees.
+        match destination.estimate_fee(&tx).await {
+            Ok(fee) => {
+                if let Some(reason) = fee.underpriced_reason(interop_tx) {
+                    println!("!! Underpriced msg {:?}: {}", msg.msg_hash, reason);
+                    return MessageStatus::Underpriced(reason);
+                }
+            }
+            Err(reason) => return simulation_failed(msg, reason),
+        }
+
+        // Only the transactions that passed the simulation get their account prefunded.
+        if interop_tx.destinationPaymaster.is_zero() {
+            if let Err(status) = fund_aliased_account(
+                msg,
+                interop_tx,
+                destination,
+                tx.from().unwrap(),
+                admission,
+                fee_ledger,
+            )
+            .await
+            {
+                return status;
+            }
+        }
+
+        // We do a lot of work here, as era doesn't accept 'eth_sendTransaction' and alloy really wants
+        // to sign it with some wallet.
+        // So we construct the transaction parts manually - and then send as 'raw' transaction.
+
+        tx.prep_for_submission();
+        let provider = destination.provider();
+
+        let sendable_tx = match provider.fill(tx).await {
+            Ok(sendable_tx) => sendable_tx,
+            Err(error) => return simulation_failed(msg, RevertReason::from_rpc_error(&error)),
+        };
         let transaction_request = sendable_tx.as_builder().unwrap();
 
         let unsigned_tx = transaction_request.clone().build_unsigned().unwrap();
@@ -561,19 +1434,602 @@ async fn handle_type_c_message(
 
             match p1 {
                 Ok(p1) => {
-                    let receipt = p1.get_receipt().await.unwrap();
+                    // Retry checks whether the bundle got executed - so the transaction is not sent twice.
+                    let receipt = match p1.get_receipt().await {
+                        Ok(receipt) => receipt,
+                        Err(error) => {
+                            println!(
+                                "!! No receipt for the type C tx of msg {:?}: {} - will check again",
+                                msg.msg_hash, error
+                            );
+                            return MessageStatus::Retrying(format!("no receipt: {}", error));
+                        }
+                    };
                     println!(
                         "    === Sent type C tx to: {} hash: {}",
                         destination_chain, receipt.inner.transaction_hash
-                    )
+                    );
+                    // Self-paid transactions don't cost the relayer anything.
+                    if !interop_tx.destinationPaymaster.is_zero() {
+                        let collected = match destination.get_paymaster_basic_token().await {
+                            Ok(token) => collected_fees(
+                                receipt.inner.inner.logs(),
+                                token,
+                                interop_tx.destinationPaymaster,
+                            ),
+                            Err(error) => {
+                                println!(
+                                    "!! Can't read the paymaster token on chain {} - collected fee not recorded: {}",
+                                    destination_chain, error
+                                );
+                                U256::ZERO
+                            }
+                        };
+                        fee_ledger
+                            .record(FeeRecord {
+                                timestamp: fees::now(),
+                                msg_hash: msg.msg_hash,
+                                source_chain: msg.chain_id,
+                                destination_chain,
+                                kind: FeeKind::TypeC,
+                                tx_hash: receipt.transaction_hash(),
+                                cost: receipt_cost(&receipt),
+                                collected,
+                                base_token_price: destination.base_token_price(),
+                            })
+                            .await;
+                    }
+                    MessageStatus::Relayed
                 }
                 Err(error) => {
-                    println!("!! ERROR - {}", error);
+                    let reason = RevertReason::from_rpc_error(&error);
+                    println!(
+                        "!! Submitting type C tx for msg {:?} failed: {}",
+                        msg.msg_hash,
+                        reason.describe()
+                    );
+                    if reason.action() == RevertAction::RetryLater {
+                        MessageStatus::Retrying(reason.describe())
+                    } else {
+                        MessageStatus::Failed(reason.describe())
+                    }
                 }
             }
         } else {
             panic!("Wrong type");
         }
+    } else {
+        // Nothing to do - bundles were already executed.
+        MessageStatus::Relayed
+    }
+}
+
+// Self-paid type C transaction - the aliased account must have enough base token for the gas.
+// Missing amount is sent by the relayer, only within the prefund limits (and only once per message).
+async fn fund_aliased_account(
+    msg: &InteropMessageParsed,
+    interop_tx: &InteropCenter::InteropTransaction,
+    destination: &InteropChain,
+    account: Address,
+    admission: &Admission,
+    fee_ledger: &FeeLedger,
+) -> Result<(), MessageStatus> {
+    // FIXME: add the value, once it is passed.
+    let required = interop_tx.gasLimit.saturating_mul(interop_tx.gasPrice);
+    let balance = match destination.get_balance(account).await {
+        Ok(balance) => balance,
+        Err(error) => {
+            println!(
+                "!! Can't read the balance of aliased account {} on chain {}: {}",
+                account, destination.chain_id, error
+            );
+            return Err(MessageStatus::Retrying(format!(
+                "balance of aliased account {} unknown: {}",
+                account, error
+            )));
+        }
+    };
+    if balance >= required {
+        return Ok(());
+    }
+    let missing = required - balance;
+    if missing > destination.cents_to_tokens(admission.config.max_prefund_cents) {
+        let reason = format!(
+            "aliased account {} has {}, needs {} for the gas",
+            account,
+            to_human_size(balance),
+            to_human_size(required)
+        );
+        println!("  Can't relay msg {:?} yet: {}", msg.msg_hash, reason);
+        return Err(MessageStatus::Retrying(reason));
+    }
+    if let Err(limit) = admission
+        .reserve_prefund(
+            msg.msg_hash,
+            msg.chain_id,
+            interop_tx.sourceChainSender,
+            destination,
+            missing,
+        )
+        .await
+    {
+        let reason = format!(
+            "aliased account {} has {}, needs {} for the gas ({})",
+            account,
+            to_human_size(balance),
+            to_human_size(required),
+            limit
+        );
+        println!("!! Refusing msg {:?}: {}", msg.msg_hash, reason);
+        return Err(MessageStatus::Refused(reason));
+    }
+
+    let admin_provider = zksync_provider()
+        .with_recommended_fillers()
+        .wallet(destination.admin_wallet.clone())
+        .on_http(destination.rpc().parse().unwrap());
+    let tx = TransactionRequest::default()
+        .with_to(account)
+        .with_value(missing);
+    let receipt = match admin_provider.send_transaction(tx).await {
+        Ok(pending) => pending
+            .get_receipt()
+            .await
+            .map_err(|error| error.to_string()),
+        Err(error) => {
+            // Nothing was sent - the prefund can be tried again.
+            admission
+                .release_prefund(
+                    msg.msg_hash,
+                    msg.chain_id,
+                    interop_tx.sourceChainSender,
+                    destination.chain_id,
+                    missing,
+                )
+                .await;
+            Err(error.to_string())
+        }
+    };
+    match receipt {
+        Ok(receipt) => {
+            println!(
+                "  Prefunded aliased account {} with {} tx {:?}",
+                account,
+                to_human_size(missing),
+                receipt.transaction_hash()
+            );
+            fee_ledger
+                .record(FeeRecord {
+                    timestamp: fees::now(),
+                    msg_hash: msg.msg_hash,
+                    source_chain: msg.chain_id,
+                    destination_chain: destination.chain_id,
+                    kind: FeeKind::AliasedAccountPrefund,
+                    tx_hash: receipt.transaction_hash(),
+                    cost: missing + receipt_cost(&receipt),
+                    collected: U256::ZERO,
+                    base_token_price: destination.base_token_price(),
+                })
+                .await;
+            Ok(())
+        }
+        Err(error) => {
+            let reason = format!("prefunding aliased account {} failed: {}", account, error);
+            println!("!! {}", reason);
+            Err(MessageStatus::Retrying(reason))
+        }
+    }
+}
+
+// What the transaction cost (in the base token of its chain).
+fn receipt_cost(receipt: &impl ReceiptResponse) -> U256 {
+    U256::from(receipt.gas_used()) * U256::from(receipt.effective_gas_price())
+}
+
+// Paymaster tokens that the paymaster took from the user (in the type C transaction).
+fn collected_fees(logs: &[Log], token: Address, paymaster: Address) -> U256 {
+    logs.iter()
+        .filter(|log| log.address() == token)
+        .filter_map(|log| PaymasterToken::Transfer::decode_log(&log.inner, true).ok())
+        .filter(|transfer| transfer.to == paymaster)
+        .map(|transfer| transfer.value)
+        .sum()
+}
+
+fn simulation_failed(msg: &InteropMessageParsed, reason: RevertReason) -> MessageStatus {
+    match reason.action() {
+        RevertAction::RetryLater => {
+            println!(
+                "  Simulation of msg {:?} failed: {} - will retry",
+                msg.msg_hash,
+                reason.describe()
+            );
+            MessageStatus::Retrying(reason.describe())
+        }
+        RevertAction::Skip => {
+            println!(
+                "  Simulation of msg {:?} failed: {} - nothing to do",
+                msg.msg_hash,
+                reason.describe()
+            );
+            MessageStatus::Relayed
+        }
+        RevertAction::Alert => {
+            println!(
+                "!! ALERT: simulation of msg {:?} from chain {} failed: {}",
+                msg.msg_hash,
+                msg.chain_id,
+                reason.describe()
+            );
+            MessageStatus::Failed(format!("simulation failed: {}", reason.describe()))
+        }
+    }
+}
+
+// Everything that is needed to process the interop messages - shared between the listeners and the admin API.
+#[derive(Clone)]
+pub struct Relayer {
+    pub providers_map: SharedChains,
+    pub shared_map: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>>,
+    pub in_flight: Arc<InFlight>,
+    // Logs that failed the integrity checks (reported through the admin API).
+    pub rejected_logs: Arc<Mutex<Vec<RejectedLog>>>,
+    pub settings: Arc<ChainSettings>,
+    pub policy: Arc<TrustPolicy>,
+    pub treasury: Arc<Treasury>,
+    pub admission: Arc<Admission>,
+    pub rebalance: Arc<RebalanceConfig>,
+    // Held while the paymaster tokens are being rebalanced.
+    pub rebalancing: Arc<Mutex<()>>,
+    pub fee_ledger: Arc<FeeLedger>,
+    pub accounts: Arc<AccountManager>,
+    pub streaming: bool,
+    pub shutdown: watch::Receiver<bool>,
+    pub listeners: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    // Retries waiting for their turn - a message has at most one.
+    pub retries: Arc<Mutex<HashMap<FixedBytes<32>, JoinHandle<()>>>>,
+    // Other work started in the background (treasury top-ups, admin requests) - awaited on shutdown.
+    pub background: Arc<std::sync::Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Relayer {
+    // Snapshot of the chains that are currently relayed.
+    pub async fn chains(&self) -> HashMap<u64, Arc<InteropChain>> {
+        self.providers_map.read().await.clone()
+    }
+
+    pub async fn chain(&self, chain_id: u64) -> Option<Arc<InteropChain>> {
+        self.providers_map.read().await.get(&chain_id).cloned()
+    }
+
+    pub async fn start_listener(&self, chain: Arc<InteropChain>, resume_from: Option<u64>) {
+        let relayer = self.clone();
+        let handle = tokio::task::spawn(async move {
+            let chain_id = chain.chain_id;
+            chain
+                .listen_on_interop_messages(
+                    relayer.streaming,
+                    resume_from,
+                    relayer.shutdown.clone(),
+                    |log| {
+                        let relayer = relayer.clone();
+                        async move {
+                            relayer.process_log(log, chain_id).await;
+                        }
+                    },
+                )
+                .await;
+        });
+        self.listeners.lock().await.push(handle);
+    }
+
+    // Keeps the paymaster of the chain funded (in the background).
+    pub fn start_treasury(&self, chain: Arc<InteropChain>) {
+        self.spawn_background(self.treasury.clone().run(chain, self.shutdown.clone()));
+    }
+
+    pub fn spawn_background(
+        &self,
+        task: impl futures::future::Future<Output = ()> + Send + 'static,
+    ) {
+        let mut background = self.background.lock().unwrap();
+        background.retain(|handle| !handle.is_finished());
+        background.push(tokio::task::spawn(task));
+    }
+
+    pub async fn wait_for_background(&self) {
+        while !self
+            .background
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|handle| handle.is_finished())
+        {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    // Messages that are not done yet - Pending or waiting for a retry.
+    pub async fn unfinished_messages(&self) -> Vec<InteropMessageParsed> {
+        self.shared_map
+            .lock()
+            .await
+            .values()
+            .filter(|msg| !msg.status.is_final())
+            .cloned()
+            .collect()
+    }
+
+    // Starts relaying to and from a chain that joined the network.
+    // Returns false if the chain is already relayed.
+    pub async fn add_chain(&self, chain: Arc<InteropChain>) -> bool {
+        {
+            let mut chains = self.providers_map.write().await;
+            if chains.contains_key(&chain.chain_id) {
+                return false;
+            }
+            chains.insert(chain.chain_id, chain.clone());
+        }
+        tokio::task::spawn(chain.endpoints.clone().run_health_checks());
+        self.start_treasury(chain.clone());
+        self.start_listener(chain, None).await;
+        true
+    }
+
+    // Stops relaying to and from the chain. Returns false if the chain was not relayed.
+    pub async fn remove_chain(&self, chain_id: u64) -> bool {
+        match self.providers_map.write().await.remove(&chain_id) {
+            Some(chain) => {
+                chain.removed.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns once all the listeners have stopped.
+    pub async fn wait_for_listeners(&self) {
+        while !self
+            .listeners
+            .lock()
+            .await
+            .iter()
+            .all(|handle| handle.is_finished())
+        {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    pub async fn process_log(&self, log: Log, chain_id: u64) {
+        let msg = match InteropMessageParsed::from_log(&log, chain_id) {
+            Ok(msg) => msg,
+            Err(error) => {
+                println!(
+                    "!! Rejecting log from chain {} (block {:?}, tx {:?}): {}",
+                    chain_id, log.block_number, log.transaction_hash, error
+                );
+                self.rejected_logs.lock().await.push(RejectedLog {
+                    chain_id,
+                    block_number: log.block_number,
+                    transaction_hash: log.transaction_hash,
+                    log_index: log.log_index,
+                    reason: error.to_string(),
+                });
+                return;
+            }
+        };
+
+        if msg.source_log_removed {
+            handle_removed_message(&msg, self.shared_map.clone()).await;
+            return;
+        }
+
+        println!(
+            "Got msg from chain: {:?} id:{} hash: {:?} ",
+            msg.chain_id, msg.interop_message.messageNum, msg.msg_hash
+        );
+        self.process_message(msg).await;
+    }
+
+    pub async fn process_message(&self, mut msg: InteropMessageParsed) {
+        {
+            // Claimed under the lock - so that the message is never processed twice at the same time.
+            let mut map = self.shared_map.lock().await;
+            match map.get(&msg.msg_hash).map(|existing| &existing.status) {
+                Some(MessageStatus::Skipped) => {
+                    println!("  Skipping msg {:?} (requested by admin)", msg.msg_hash);
+                    return;
+                }
+                Some(MessageStatus::Pending) => {
+                    println!("  Msg {:?} is already being processed", msg.msg_hash);
+                    return;
+                }
+                _ => {}
+            }
+            msg.status = MessageStatus::Pending;
+            msg.attempts += 1;
+            map.insert(msg.msg_hash, msg.clone());
+        }
+
+        let providers_map = self.chains().await;
+        let forwarded = handle_type_a_message(
+            &msg,
+            &providers_map,
+            &self.policy,
+            &self.in_flight,
+            &self.fee_ledger,
+        )
+        .await;
+
+        // Message that wasn't forwarded everywhere stays Failed - it can be retried through the admin API.
+        msg.status = if let Err(status) = forwarded {
+            status
+        } else if let Some(interop_tx) = &msg.interop_transaction {
+            // Checked in from_log.
+            let destination_chain: u64 = interop_tx.destinationChain.saturating_to();
+            if !self.policy.allows(msg.chain_id, destination_chain) {
+                let reason = format!(
+                    "chain {} doesn't accept messages from chain {}",
+                    destination_chain, msg.chain_id
+                );
+                println!("!! Refusing msg {:?}: {}", msg.msg_hash, reason);
+                MessageStatus::Refused(reason)
+            } else if !providers_map.contains_key(&destination_chain) {
+                let reason = format!("chain {} is not relayed", destination_chain);
+                println!("!! Can't relay msg {:?}: {}", msg.msg_hash, reason);
+                MessageStatus::Failed(reason)
+            } else if let Err(status) = self
+                .admit(&msg, interop_tx, &providers_map[&destination_chain])
+                .await
+            {
+                status
+            } else {
+                handle_type_c_message(
+                    &msg,
+                    interop_tx,
+                    &providers_map[&destination_chain],
+                    self.shared_map.clone(),
+                    &self.in_flight,
+                    &self.accounts,
+                    &self.fee_ledger,
+                    &self.admission,
+                )
+                .await
+            }
+        } else {
+            MessageStatus::Relayed
+        };
+
+        let mut map = self.shared_map.lock().await;
+        // The operator might have skipped the message in the meantime.
+        if map
+            .get(&msg.msg_hash)
+            .is_some_and(|existing| existing.status == MessageStatus::Skipped)
+        {
+            println!("  Msg {:?} was skipped while being processed", msg.msg_hash);
+            return;
+        }
+        if let MessageStatus::Retrying(reason) = &msg.status {
+            if msg.attempts >= MAX_ATTEMPTS {
+                msg.status = MessageStatus::Failed(format!(
+                    "{} (gave up after {} attempts)",
+                    reason, msg.attempts
+                ));
+            } else {
+                self.schedule_retry(msg.clone()).await;
+            }
+        }
+        map.insert(msg.msg_hash, msg);
+    }
+
+    // Checks the type C transaction against the admission limits - returns the status of the message
+    // that must not be submitted.
+    async fn admit(
+        &self,
+        msg: &InteropMessageParsed,
+        interop_tx: &InteropCenter::InteropTransaction,
+        destination: &InteropChain,
+    ) -> Result<(), MessageStatus> {
+        let preferred_paymaster = match destination.get_preferred_paymaster().await {
+            Ok(paymaster) => paymaster,
+            Err(error) => {
+                println!(
+                    "!! Can't read the preferred paymaster of chain {}: {}",
+                    destination.chain_id, error
+                );
+                return Err(MessageStatus::Retrying(format!(
+                    "preferred paymaster unknown: {}",
+                    error
+                )));
+            }
+        };
+        if let Err(reason) = self
+            .admission
+            .check(
+                msg.chain_id,
+                interop_tx,
+                destination.chain_id,
+                preferred_paymaster,
+                msg.attempts > 1,
+            )
+            .await
+        {
+            println!("!! Refusing msg {:?}: {}", msg.msg_hash, reason);
+            return Err(MessageStatus::Refused(reason));
+        }
+        Ok(())
+    }
+
+    async fn schedule_retry(&self, msg: InteropMessageParsed) {
+        let msg_hash = msg.msg_hash;
+        let relayer = self.clone();
+        // Boxed, as process_message would otherwise contain its own future.
+        let retry: BoxFuture<'static, ()> = Box::pin(async move {
+            tokio::time::sleep(RETRY_DELAY).await;
+            // Gone if the retry was cancelled in the meantime.
+            if relayer.retries.lock().await.remove(&msg.msg_hash).is_none() {
+                return;
+            }
+            if *relayer.shutdown.borrow() {
+                return;
+            }
+            relayer.process_message(msg).await;
+        });
+        // Spawned under the lock - so that the retry can't take itself out before it is in.
+        let mut retries = self.retries.lock().await;
+        if let Some(previous) = retries.insert(msg_hash, tokio::task::spawn(retry)) {
+            previous.abort();
+        }
+    }
+
+    // Cancels the scheduled retry of the message (if any).
+    pub async fn cancel_retry(&self, msg_hash: &FixedBytes<32>) {
+        if let Some(retry) = self.retries.lock().await.remove(msg_hash) {
+            retry.abort();
+        }
+    }
+
+    // Fetches and processes (again) all the interop logs from the given block range.
+    pub async fn rescan(
+        &self,
+        chain_id: u64,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<usize, EndpointError> {
+        let chain = self.chain(chain_id).await.unwrap();
+        let filter = chain
+            .interop_logs_filter()
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = chain.get_logs(&filter).await?;
+        let count = logs.len();
+        for log in logs {
+            self.process_log(log, chain_id).await;
+        }
+        Ok(count)
+    }
+}
+
+// Called when the log that emitted the message was removed from the source chain (reorg).
+async fn handle_removed_message(
+    msg: &InteropMessageParsed,
+    shared_map: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>>,
+) {
+    let mut map = shared_map.lock().await;
+    match map.get_mut(&msg.msg_hash) {
+        Some(relayed) => {
+            println!(
+                "!! Message from chain: {:?} id:{} hash: {:?} was already relayed, but its source log disappeared (reorg)",
+                msg.chain_id, msg.interop_message.messageNum, msg.msg_hash
+            );
+            relayed.source_log_removed = true;
+        }
+        None => {
+            println!(
+                "  Message from chain: {:?} hash: {:?} removed by reorg before relaying",
+                msg.chain_id, msg.msg_hash
+            );
+        }
     }
 }
 
@@ -582,25 +2038,290 @@ async fn handle_type_c_message(
 #[command(version = "1.0")]
 #[command(about = "Handles RPC URLs and interop Ethereum addresses")]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// List of RPC URL and interop address pairs (e.g. -r URL ADDRESS)
+    /// Multiple endpoints of the same chain can be passed as comma separated URLs (-r URL1,URL2 ADDRESS).
     #[arg(short, long, num_args = 2, value_names = ["URL", "ADDRESS"])]
     rpc: Vec<String>,
 
+    // How many endpoints of a chain must agree on critical reads (executed bundles, received messages, aliased accounts).
+    #[arg(long, default_value = "1")]
+    quorum: usize,
+
     // Specify the price of the base token  (10^18) in cents.
     // For eth - you can set it to 200_000.
+    // Needed only by the relayer and add-chain (ratios of the paymaster tokens).
     #[arg(long)]
     base_token_price: Vec<u64>,
 
+    // Admin wallet - needed only by the commands that send transactions (and by the relayer).
     #[arg(long)]
-    private_key: String,
+    private_key: Option<String>,
 
     // How many assets should each paymaster hold. (default ~20USD).
+    // Paymaster is topped up to this amount (high watermark).
     #[arg(long, default_value = "2000")]
     paymaster_balance_cents: u64,
 
+    // Paymaster is topped up once its balance drops below this (low watermark).
+    #[arg(long, default_value = "1000")]
+    paymaster_low_balance_cents: u64,
+
+    // Maximum amount sent to the paymaster of each chain per day.
+    #[arg(long, default_value = "20000")]
+    paymaster_daily_cap_cents: u64,
+
+    // Alert when the admin wallet (that funds the paymasters) has less than this.
+    #[arg(long, default_value = "10000")]
+    funding_alert_cents: u64,
+
+    // Paymaster that type C transactions may use, besides the preferred paymaster of the destination chain.
+    // Can be repeated.
+    #[arg(long)]
+    allowed_paymaster: Vec<Address>,
+
+    // Type C transactions with a higher gas limit are refused.
+    #[arg(long, default_value = "20000000")]
+    max_gas_limit: u64,
+
+    // Type C transactions with a higher gas price (in wei) are refused.
+    #[arg(long, default_value = "100000000000")]
+    max_gas_price: u64,
+
+    // Self-paid type C transactions (no paymaster): the relayer sends the aliased account up to this
+    // much, if it doesn't have enough for the gas. Disabled by default.
+    #[arg(long, default_value = "0")]
+    max_prefund_cents: u64,
+
+    // Maximum that is prefunded to the aliased accounts of a single source chain sender,
+    // per destination chain and (UTC) day.
+    #[arg(long, default_value = "500")]
+    max_prefund_per_sender_per_day_cents: u64,
+
+    // Type C transactions from a single source chain sender, above this rate, are refused.
+    #[arg(long, default_value = "10")]
+    max_tx_per_sender_per_minute: usize,
+
+    // Seconds between sweeps of the paymaster tokens that the paymasters collect (disabled if not set).
+    // Surplus tokens are sent to the paymasters that hold less than the reserve, or redeemed.
+    #[arg(long)]
+    rebalance_interval: Option<u64>,
+
+    // Paymaster tokens that stay with each paymaster when sweeping.
+    #[arg(long, default_value = "0")]
+    paymaster_token_reserve_cents: u64,
+
+    // Paymaster tokens are swept only once the surplus is worth at least this.
+    #[arg(long, default_value = "500")]
+    min_sweep_cents: u64,
+
+    // JSON file with the base token prices in cents per chain (e.g. {"500": 200000, "501": 100}).
+    // Re-read when it changes - the prices from --base-token-price are used until then.
+    #[arg(long, conflicts_with = "price_url")]
+    price_file: Option<PathBuf>,
+
+    // URL that returns the base token prices (in the same format as the --price-file).
+    #[arg(long)]
+    price_url: Option<String>,
+
+    // Seconds between the price checks.
+    #[arg(long, default_value = "300")]
+    price_interval: u64,
+
+    // Paymaster token ratios are updated when they are off by more than this (in basis points).
+    #[arg(long, default_value = "100")]
+    price_drift_bps: u64,
+
+    // Seconds between the paymaster balance checks.
+    #[arg(long, default_value = "60")]
+    treasury_interval: u64,
+
     // If true - use streaming to get logs (lower latency, but doens't work well on public networks).
     #[arg(long)]
     streaming: bool,
+
+    // Seconds between polls for new logs (when not streaming).
+    // Either a single value for all chains, or one per --rpc.
+    #[arg(long)]
+    poll_interval: Vec<u64>,
+
+    // How many blocks behind the head a log must be before it is processed (when not streaming).
+    // Either a single value for all chains, or one per --rpc.
+    #[arg(long)]
+    confirmations: Vec<u64>,
+
+    // File where the relayer keeps its checkpoints (last processed block per chain) between restarts.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    // Address to serve the /healthz and /readyz endpoints on (e.g. 127.0.0.1:8080).
+    #[arg(long)]
+    http_address: Option<SocketAddr>,
+
+    // Address to serve the admin API on - should be a local one (e.g. 127.0.0.1:8081).
+    #[arg(long)]
+    admin_address: Option<SocketAddr>,
+
+    // Apply the setup plan (trust, paymasters, paymaster tokens). Without it, only the differences are printed.
+    // Also used by the 'topology import'.
+    #[arg(long)]
+    apply: bool,
+
+    // JSON file with the chains that each chain accepts messages from (e.g. {"accept_from": {"501": [500]}}),
+    // every chain accepts its own messages.
+    // Without it, all the chains trust each other (and themselves).
+    #[arg(long)]
+    trust_policy: Option<PathBuf>,
+
+    // File where the costs and collected fees of the relayed transactions are appended (for 'report fees').
+    #[arg(long)]
+    fee_ledger: Option<PathBuf>,
+
+    // zkEVM artifacts (from 'forge build --zksync'), instead of the ones embedded at build time.
+    // Aliased account addresses are derived from the InteropAccount bytecode in there - each chain is
+    // asked for its bytecode hash only when there are no artifacts at all.
+    #[arg(long)]
+    artifacts: Option<PathBuf>,
+
+    // How many seconds to wait for in-flight transactions, when shutting down.
+    #[arg(long, default_value = "60")]
+    shutdown_timeout: u64,
+}
+
+// Without a command - CLI sets up the chains and runs the relayer.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export or import the network topology (contracts on each chain and how they are wired together).
+    Topology {
+        #[command(subcommand)]
+        command: TopologyCommand,
+    },
+    /// Deploys the interop center, paymaster and paymaster token to the given chains (at the same addresses).
+    Deploy {
+        /// RPC URL of the chain to deploy to (repeat for each chain).
+        #[arg(long = "url", required = true)]
+        urls: Vec<String>,
+        /// Directory with the zkEVM artifacts (from 'forge build --zksync'), instead of the embedded ones.
+        #[arg(long)]
+        artifacts: Option<PathBuf>,
+        /// CREATE2 salt - use a different one to deploy a fresh set of contracts.
+        #[arg(long)]
+        salt: Option<B256>,
+        /// File to write the resulting config to (stdout if not set).
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Wires a new chain with the configured ones (use --apply to send the transactions).
+    AddChain {
+        /// RPC URL of the new chain (comma separated for multiple endpoints).
+        rpc: String,
+        /// Interop center address on the new chain.
+        address: Address,
+        /// Price of the base token (10^18) of the new chain in cents.
+        base_token_price: u64,
+        /// Admin API of the running relayer (e.g. http://127.0.0.1:8081) - to start relaying the new chain.
+        #[arg(long)]
+        relayer_admin: Option<String>,
+    },
+    /// Reports based on the data collected by the relayer.
+    Report {
+        #[command(subcommand)]
+        command: ReportCommand,
+    },
+    /// Clears the trust, paymasters and token bridges of the departing chain on the configured ones
+    /// (use --apply to send the transactions).
+    RemoveChain {
+        chain_id: u64,
+        /// Admin API of the running relayer (e.g. http://127.0.0.1:8081) - to stop relaying the chain.
+        #[arg(long)]
+        relayer_admin: Option<String>,
+    },
+    /// Aliased accounts of the source chain senders on the configured chains.
+    Accounts {
+        #[command(subcommand)]
+        command: AccountsCommand,
+    },
+    /// Prints the aliased address of the address from the source chain on every configured chain.
+    Alias { chain: u64, address: Address },
+}
+
+#[derive(Subcommand, Debug)]
+enum TopologyCommand {
+    /// Reads the topology of the configured chains into a JSON manifest.
+    Export {
+        /// File to write the manifest to (stdout if not set).
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Wires the configured chains according to the manifest (use --apply to send the transactions).
+    Import { manifest: PathBuf },
+}
+
+#[derive(Subcommand, Debug)]
+enum ReportCommand {
+    /// Profit / loss of the relayed transactions per chain pair (needs --fee-ledger).
+    Fees {
+        /// Unix timestamp, or how long ago (e.g. 12h, 7d).
+        #[arg(long, default_value = "1d")]
+        since: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AccountsCommand {
+    /// Aliased accounts that the relayer came across (needs --state-file), with their deployment status.
+    List,
+    /// Aliased account of the sender on each of the configured chains.
+    Show { source_chain: u64, sender: Address },
+    /// Deploys the aliased accounts of the senders on the configured chains.
+    Deploy {
+        source_chain: u64,
+        #[arg(required = true)]
+        senders: Vec<Address>,
+        /// Deploy only on this chain.
+        #[arg(long)]
+        chain: Option<u64>,
+    },
+}
+
+impl Command {
+    // Whether the command sends transactions from the admin wallet (the relayer itself always does).
+    fn sends_transactions(&self, apply: bool) -> bool {
+        match self {
+            Command::Deploy { .. }
+            | Command::Accounts {
+                command: AccountsCommand::Deploy { .. },
+            } => true,
+            Command::Topology {
+                command: TopologyCommand::Import { .. },
+            }
+            | Command::AddChain { .. }
+            | Command::RemoveChain { .. } => apply,
+            Command::Topology {
+                command: TopologyCommand::Export { .. },
+            }
+            | Command::Report { .. }
+            | Command::Accounts { .. }
+            | Command::Alias { .. } => false,
+        }
+    }
+
+    // Whether the command needs the base token prices of the configured chains.
+    fn needs_prices(&self) -> bool {
+        matches!(self, Command::AddChain { .. })
+    }
+}
+
+// Returns the setting for the chain with a given index - settings can be either skipped (default),
+// passed once (used for all the chains) or passed once per chain.
+fn per_chain_setting(values: &[u64], index: usize, default: u64) -> u64 {
+    match values.len() {
+        0 => default,
+        1 => values[0],
+        _ => values[index],
+    }
 }
 
 pub fn to_human_size(input: U256) -> String {
@@ -624,19 +2345,64 @@ pub fn to_human_size(input: U256) -> String {
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let mut rpc_addresses = Vec::new();
+    // Reports are based on the relayer's files only - no chains needed.
+    if let Some(Command::Report { command }) = &cli.command {
+        match command {
+            ReportCommand::Fees { since } => {
+                let Some(fee_ledger) = &cli.fee_ledger else {
+                    anyhow::bail!("Pass the --fee-ledger that the relayer writes to");
+                };
+                fees::report(fee_ledger, fees::parse_since(since)?)?
+            }
+        }
+        return Ok(());
+    }
 
-    let private_key = cli
-        .private_key
-        .strip_prefix("0x")
-        .unwrap_or(&cli.private_key);
+    let mut rpc_addresses = Vec::new();
 
-    let signer: PrivateKeySigner = PrivateKeySigner::from_signing_key(
-        // private key from account 7.
-        SigningKey::from_bytes(Vec::from_hex(private_key).unwrap().as_slice().into()).unwrap(),
-    );
+    let signer: PrivateKeySigner = match &cli.private_key {
+        Some(private_key) => {
+            let private_key = private_key.strip_prefix("0x").unwrap_or(private_key);
+            PrivateKeySigner::from_signing_key(
+                // private key from account 7.
+                SigningKey::from_bytes(Vec::from_hex(private_key).unwrap().as_slice().into())
+                    .unwrap(),
+            )
+        }
+        None => {
+            anyhow::ensure!(
+                !cli.command
+                    .as_ref()
+                    .map_or(true, |command| command.sends_transactions(cli.apply)),
+                "Pass the --private-key of the admin wallet"
+            );
+            // Never signs anything - the read-only commands just need some wallet for the providers.
+            PrivateKeySigner::random()
+        }
+    };
+    let admin_address = signer.address();
     let admin_wallet = ZksyncWallet::from(signer);
 
+    // Deploys to its own list of chains.
+    if let Some(Command::Deploy {
+        urls,
+        artifacts,
+        salt,
+        output,
+    }) = &cli.command
+    {
+        deploy::deploy(
+            urls,
+            artifacts.as_deref(),
+            salt.unwrap_or_default(),
+            admin_wallet,
+            admin_address,
+            output.as_deref(),
+        )
+        .await?;
+        return Ok(());
+    }
+
     // Process URL-address pairs
     for chunk in cli.rpc.chunks(2) {
         if chunk.len() == 2 {
@@ -649,266 +2415,642 @@ async fn main() -> anyhow::Result<()> {
             eprintln!("Each RPC URL must be paired with an Ethereum address.");
         }
     }
-    assert_eq!(
-        rpc_addresses.len(),
-        cli.base_token_price.len(),
-        "Specify as many --base-token-price as --rpc-addresses"
-    );
-
-    let mut providers_map = HashMap::new();
-
-    for ((rpc, interop_address), base_token_price) in rpc_addresses
-        .into_iter()
-        .zip(cli.base_token_price.into_iter())
+    let base_token_prices = if cli.base_token_price.is_empty()
+        && !cli.command.as_ref().map_or(true, Command::needs_prices)
     {
-        let provider = zksync_provider()
-            .with_recommended_fillers()
-            .on_http(rpc.clone().parse().unwrap());
-
-        let chain_id = provider.get_chain_id().await?;
-
-        let base_token: U256 = 1_000_000_000_000_000_000u64.try_into().unwrap();
+        vec![0; rpc_addresses.len()]
+    } else {
+        assert_eq!(
+            rpc_addresses.len(),
+            cli.base_token_price.len(),
+            "Specify as many --base-token-price as --rpc-addresses"
+        );
+        cli.base_token_price.clone()
+    };
+    for (name, values) in [
+        ("--poll-interval", &cli.poll_interval),
+        ("--confirmations", &cli.confirmations),
+    ] {
+        assert!(
+            values.len() <= 1 || values.len() == rpc_addresses.len(),
+            "Specify {} once, or as many times as --rpc-addresses",
+            name
+        );
+    }
 
-        let tokens_for_paymaster = base_token
-            .checked_mul(cli.paymaster_balance_cents.try_into().unwrap())
-            .unwrap()
-            .checked_div(base_token_price.try_into().unwrap())
-            .unwrap();
+    let settings = Arc::new(ChainSettings {
+        admin_wallet,
+        admin_address,
+        quorum: cli.quorum,
+        poll_interval: Duration::from_secs(match cli.poll_interval.as_slice() {
+            [value] => *value,
+            _ => 30,
+        }),
+        confirmations: match cli.confirmations.as_slice() {
+            [value] => *value,
+            _ => 0,
+        },
+    });
 
-        let prev = providers_map.insert(
-            chain_id,
-            Arc::new(InteropChain {
-                provider,
-                interop_address,
-                rpc: rpc.clone(),
-                chain_id,
-                admin_wallet: admin_wallet.clone(),
-                base_token_price,
-                tokens_for_paymaster,
-            }),
-        );
+    let mut providers_map = HashMap::new();
 
-        println!(
-            "Interop on chain {}. paymaster tokens: {}",
-            chain_id,
-            to_human_size(tokens_for_paymaster)
-        );
+    for (index, ((rpc, interop_address), base_token_price)) in
+        rpc_addresses.into_iter().zip(base_token_prices).enumerate()
+    {
+        let chain = InteropChain::connect(
+            &rpc,
+            interop_address,
+            base_token_price,
+            Duration::from_secs(per_chain_setting(&cli.poll_interval, index, 30)),
+            per_chain_setting(&cli.confirmations, index, 0),
+            &settings,
+        )
+        .await?;
+        let chain_id = chain.chain_id;
+        let endpoints = chain.endpoints.clone();
+
+        let prev = providers_map.insert(chain_id, Arc::new(chain));
         if let Some(prev) = prev {
             panic!(
                 "Two interops with the same chain id {} -- {} and {} ",
-                chain_id, rpc, prev.rpc
+                chain_id,
+                rpc,
+                prev.rpc()
             );
         }
+        tokio::task::spawn(endpoints.run_health_checks());
     }
 
-    // Setup trust between inteops.
-    for (_, source_chain) in &providers_map {
-        for (_, destination_chain) in &providers_map {
-            let admin_provider = zksync_provider()
-                .with_recommended_fillers()
-                .wallet(destination_chain.admin_wallet.clone())
-                .on_http(destination_chain.rpc.parse().unwrap());
+    let policy = match &cli.trust_policy {
+        Some(path) => TrustPolicy::load(path)?,
+        None => TrustPolicy::FullMesh,
+    };
+    policy.check(&providers_map);
 
-            let contract = InteropCenter::new(destination_chain.interop_address, &admin_provider);
+    // Fail early if any of the addresses doesn't point to our contracts.
+    if !providers_map.is_empty() {
+        validate::validate(&providers_map).await?;
+    }
 
-            // TODO: before sending, maybe check if trust was already set?
-            let current_trusted_source = contract
-                .trustedSources(source_chain.chain_id.try_into().unwrap())
-                .call()
-                .await
-                .unwrap()
-                ._0;
-
-            if current_trusted_source != source_chain.interop_address {
-                let tx_hash = contract
-                    .addTrustedSource(
-                        source_chain.chain_id.try_into().unwrap(),
-                        source_chain.interop_address,
-                    )
-                    .send()
-                    .await
-                    .unwrap()
-                    .watch()
-                    .await
-                    .unwrap();
+    // Only the commands that derive the aliased accounts need it.
+    let artifacts_bytecode_hash =
+        || deploy::interop_account_bytecode_hash(cli.artifacts.as_deref());
 
-                println!(
-                    "Trust from {} to {} tx {:?}",
-                    source_chain.chain_id, destination_chain.chain_id, tx_hash
-                );
-            } else {
-                println!(
-                    "Trust from {} to {}  - SKIP",
-                    source_chain.chain_id, destination_chain.chain_id
-                );
+    match &cli.command {
+        Some(Command::Topology { command }) => {
+            match command {
+                TopologyCommand::Export { output } => {
+                    topology::export(&providers_map, output.as_deref()).await?
+                }
+                TopologyCommand::Import { manifest } => {
+                    topology::import(&providers_map, manifest, cli.apply).await?
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::AddChain {
+            rpc,
+            address,
+            base_token_price,
+            relayer_admin,
+        }) => {
+            let new_chain = InteropChain::connect(
+                rpc,
+                *address,
+                *base_token_price,
+                settings.poll_interval,
+                settings.confirmations,
+                &settings,
+            )
+            .await?;
+            let report = validate::validate_chain(&new_chain).await;
+            report.print();
+            anyhow::ensure!(
+                report.is_ok(),
+                "Contract validation failed on chain {}",
+                new_chain.chain_id
+            );
+            let request = AddChainRequest {
+                rpc: rpc.clone(),
+                interop_address: *address,
+                base_token_price: *base_token_price,
+            };
+            membership::add_chain(
+                providers_map,
+                Arc::new(new_chain),
+                &policy,
+                request,
+                cli.apply,
+                relayer_admin.as_deref(),
+            )
+            .await?;
+            return Ok(());
+        }
+        Some(Command::RemoveChain {
+            chain_id,
+            relayer_admin,
+        }) => {
+            membership::remove_chain(
+                providers_map,
+                *chain_id,
+                cli.apply,
+                relayer_admin.as_deref(),
+            )
+            .await?;
+            return Ok(());
+        }
+        Some(Command::Accounts { command }) => {
+            match command {
+                AccountsCommand::List => {
+                    let Some(state_file) = &cli.state_file else {
+                        anyhow::bail!("Pass the --state-file that the relayer writes to");
+                    };
+                    let state = RelayerState::load(state_file)?;
+                    let manager =
+                        AccountManager::new(state.aliased_accounts, artifacts_bytecode_hash()?);
+                    accounts::list(&providers_map, &manager).await
+                }
+                AccountsCommand::Show {
+                    source_chain,
+                    sender,
+                } => {
+                    let manager = AccountManager::new(vec![], artifacts_bytecode_hash()?);
+                    accounts::show(&providers_map, &manager, *source_chain, *sender).await
+                }
+                AccountsCommand::Deploy {
+                    source_chain,
+                    senders,
+                    chain,
+                } => {
+                    let manager = AccountManager::new(vec![], artifacts_bytecode_hash()?);
+                    accounts::deploy(&providers_map, &manager, *source_chain, senders, *chain)
+                        .await?
+                }
             }
+            return Ok(());
         }
+        Some(Command::Alias { chain, address }) => {
+            println!("Aliased accounts of {} from chain {}:", address, chain);
+            let manager = AccountManager::new(vec![], artifacts_bytecode_hash()?);
+            accounts::alias(&providers_map, &manager, *chain, *address).await;
+            return Ok(());
+        }
+        // Handled before connecting to the chains.
+        Some(Command::Deploy { .. }) | Some(Command::Report { .. }) => unreachable!(),
+        None => {}
+    }
+    let artifacts_bytecode_hash = artifacts_bytecode_hash()?;
+
+    // Relayer derives the aliased addresses locally - make sure that they match the contracts.
+    AccountManager::new(vec![], artifacts_bytecode_hash)
+        .cross_check(&providers_map)
+        .await?;
+
+    let chains: SharedChains = Arc::new(RwLock::new(providers_map.clone()));
+    let health_state = Arc::new(HealthState {
+        providers_map: chains.clone(),
+        wiring_verified: AtomicBool::new(false),
+    });
+    if let Some(http_address) = cli.http_address {
+        tokio::task::spawn(health::serve(
+            http_address,
+            health_routes(health_state.clone()),
+        ));
     }
 
-    // Setup info about remote paymasters too
-    for (_, source_chain) in &providers_map {
-        let source_chain_paymaster = source_chain.get_preferred_paymaster().await;
-        for (_, destination_chain) in &providers_map {
-            let admin_provider = zksync_provider()
-                .with_recommended_fillers()
-                .wallet(destination_chain.admin_wallet.clone())
-                .on_http(destination_chain.rpc.parse().unwrap());
+    // Setup trust between interops, paymasters and paymaster tokens.
+    let plan = setup::compute_plan(
+        &providers_map,
+        &policy.edges(&providers_map),
+        &policy.revoked(&providers_map),
+    )
+    .await;
+    plan.print();
+    let wiring_verified = if plan.is_empty() {
+        true
+    } else if cli.apply {
+        setup::apply_plan(&providers_map, &plan).await
+    } else {
+        println!("!! Setup is not up to date - run with --apply to apply the plan above.");
+        false
+    };
+    health_state
+        .wiring_verified
+        .store(wiring_verified, Ordering::Relaxed);
 
-            let contract = InteropCenter::new(destination_chain.interop_address, &admin_provider);
+    let shared_map: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>> =
+        Arc::new(Mutex::new(HashMap::new()));
 
-            // TODO: before sending, maybe check if trust was already set?
-            let existing_paymaster = contract
-                .preferredPaymasters(source_chain.chain_id.try_into().unwrap())
-                .call()
-                .await
-                .unwrap()
-                ._0;
-
-            if existing_paymaster != source_chain_paymaster {
-                let tx_hash = contract
-                    .setPreferredPaymaster(
-                        source_chain.chain_id.try_into().unwrap(),
-                        source_chain_paymaster,
-                    )
-                    .send()
-                    .await
-                    .unwrap()
-                    .watch()
-                    .await
-                    .unwrap();
+    let state_file = cli.state_file.clone();
+    let state = match &state_file {
+        Some(path) => RelayerState::load(path)?,
+        None => RelayerState::default(),
+    };
+    let in_flight = Arc::new(InFlight::default());
+    let treasury = Arc::new(Treasury::new(
+        TreasuryConfig {
+            low_watermark_cents: cli.paymaster_low_balance_cents,
+            high_watermark_cents: cli.paymaster_balance_cents,
+            daily_cap_cents: cli.paymaster_daily_cap_cents,
+            funding_alert_cents: cli.funding_alert_cents,
+            interval: Duration::from_secs(cli.treasury_interval),
+        },
+        state.paymaster_spend.clone(),
+    )?);
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+
+    let relayer = Relayer {
+        providers_map: chains,
+        shared_map,
+        in_flight: in_flight.clone(),
+        rejected_logs: Arc::new(Mutex::new(vec![])),
+        settings,
+        policy: Arc::new(policy),
+        treasury,
+        admission: Arc::new(Admission::new(AdmissionConfig {
+            extra_paymasters: cli.allowed_paymaster.iter().copied().collect(),
+            max_gas_limit: U256::from(cli.max_gas_limit),
+            max_gas_price: U256::from(cli.max_gas_price),
+            max_per_sender_per_minute: cli.max_tx_per_sender_per_minute,
+            max_prefund_cents: cli.max_prefund_cents,
+            max_prefund_per_sender_per_day_cents: cli.max_prefund_per_sender_per_day_cents,
+        })),
+        rebalance: Arc::new(RebalanceConfig {
+            token_reserve_cents: cli.paymaster_token_reserve_cents,
+            min_sweep_cents: cli.min_sweep_cents,
+        }),
+        rebalancing: Arc::new(Mutex::new(())),
+        fee_ledger: Arc::new(FeeLedger::new(cli.fee_ledger.clone())),
+        accounts: Arc::new(AccountManager::new(
+            state.aliased_accounts.clone(),
+            artifacts_bytecode_hash,
+        )),
+        streaming: cli.streaming,
+        shutdown: shutdown_receiver,
+        listeners: Arc::new(Mutex::new(vec![])),
+        retries: Arc::new(Mutex::new(HashMap::new())),
+        background: Arc::new(std::sync::Mutex::new(vec![])),
+    };
+    if let Some(admin_address) = cli.admin_address {
+        tokio::task::spawn(health::serve(
+            admin_address,
+            admin::admin_routes(relayer.clone()),
+        ));
+    }
 
-                println!(
-                    "Paymaster Trust from {} to {} tx {:?}",
-                    source_chain.chain_id, destination_chain.chain_id, tx_hash
-                );
-            } else {
-                println!(
-                    "Paymaster Trust from {} to {} -- SKIP",
-                    source_chain.chain_id, destination_chain.chain_id
-                );
-            }
+    let price_source: Arc<dyn PriceSource> = match (&cli.price_file, &cli.price_url) {
+        (Some(path), _) => Arc::new(FilePrices::new(path.clone())),
+        (None, Some(url)) => Arc::new(HttpPrices::new(url.clone())),
+        (None, None) => Arc::new(StaticPrices(
+            providers_map
+                .iter()
+                .map(|(chain_id, chain)| (*chain_id, chain.base_token_price()))
+                .collect(),
+        )),
+    };
+    if let Some(rebalance_interval) = cli.rebalance_interval {
+        tokio::task::spawn(rebalance::run_rebalancing(
+            relayer.clone(),
+            Duration::from_secs(rebalance_interval),
+        ));
+    }
+    tokio::task::spawn(pricing::run_price_updates(
+        relayer.clone(),
+        price_source,
+        PriceUpdateConfig {
+            interval: Duration::from_secs(cli.price_interval),
+            max_drift_bps: cli.price_drift_bps,
+            apply: cli.apply,
+        },
+    ));
+
+    for chain in providers_map.values() {
+        let resume_from = state.checkpoints.get(&chain.chain_id).copied();
+        relayer.start_treasury(chain.clone());
+        relayer.start_listener(chain.clone(), resume_from).await;
+    }
+
+    tokio::select! {
+        _ = wait_for_shutdown_signal() => {},
+        _ = relayer.wait_for_listeners() => println!("!! All listeners stopped"),
+    };
+
+    println!("Shutting down - no longer accepting new logs..");
+    shutdown_sender.send(true).unwrap();
+
+    // Retries that didn't start yet are dropped - the messages are picked up again after the restart.
+    for (_, retry) in relayer.retries.lock().await.drain() {
+        retry.abort();
+    }
+    let drained = tokio::time::timeout(Duration::from_secs(cli.shutdown_timeout), async {
+        relayer.wait_for_listeners().await;
+        relayer.wait_for_background().await;
+    })
+    .await
+    .is_ok();
+
+    let unfinished = relayer.unfinished_messages().await;
+    let mut state = state;
+    // Chains added at runtime are included too.
+    for (chain_id, chain) in &relayer.chains().await {
+        let mut checkpoint = chain.last_processed_block.load(Ordering::Relaxed);
+        // Unfinished messages must be seen again after the restart - so stop right before the oldest one.
+        if let Some(oldest) = unfinished
+            .iter()
+            .filter(|msg| msg.chain_id == *chain_id)
+            .filter_map(|msg| msg.block_number)
+            .min()
+        {
+            checkpoint = checkpoint.min(oldest.saturating_sub(1));
         }
+        // Listener might have stopped before processing anything - keep the old checkpoint then.
+        if checkpoint > 0 {
+            state.checkpoints.insert(*chain_id, checkpoint);
+        }
+    }
+    state.aliased_accounts = relayer.accounts.known().await;
+    state.paymaster_spend = relayer.treasury.daily_spend().await;
+    if let Some(path) = &state_file {
+        state.save(path)?;
+        println!("Checkpoints saved to {}", path.display());
     }
 
-    // Setup trust between 'paymaster tokens'
-    for (_, source_chain) in &providers_map {
-        let source_chain_token = source_chain.get_paymaster_basic_token().await;
-        for (_, destination_chain) in &providers_map {
-            let admin_provider = zksync_provider()
-                .with_recommended_fillers()
-                .wallet(destination_chain.admin_wallet.clone())
-                .on_http(destination_chain.rpc.parse().unwrap());
+    println!("=== Shutdown summary ===");
+    for (chain_id, checkpoint) in &state.checkpoints {
+        println!("  Chain {} processed up to block {}", chain_id, checkpoint);
+    }
+    let pending = in_flight.pending();
+    if drained && pending.is_empty() && unfinished.is_empty() {
+        println!("  No pending work.");
+    }
+    if !drained || !pending.is_empty() {
+        println!(
+            "  !! Timed out after {}s with {} operations still in flight:",
+            cli.shutdown_timeout,
+            pending.len()
+        );
+        for entry in pending {
+            println!("    - {}", entry);
+        }
+    }
+    if !unfinished.is_empty() {
+        println!(
+            "  !! {} messages not finished (seen again after the restart):",
+            unfinished.len()
+        );
+        for msg in unfinished {
+            println!(
+                "    - {:?} from chain {} (block {:?}): {:?}",
+                msg.msg_hash, msg.chain_id, msg.block_number, msg.status
+            );
+        }
+    }
 
-            let destination_chain_token: Address =
-                destination_chain.get_paymaster_basic_token().await;
+    // We have to support 2 things:
+    // * for each 'interop message' - 'deliver' it to all the other locations
+    // * for each 'type C' message - detect, create a payload and send.
 
-            let contract = PaymasterToken::new(destination_chain_token, &admin_provider);
+    Ok(())
+}
 
-            // TODO: before sending, maybe check if trust was already set?
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interop_tx(gas_limit: u64, gas_price: u64) -> InteropCenter::InteropTransaction {
+        InteropCenter::InteropTransaction {
+            sourceChainSender: Address::ZERO,
+            destinationChain: U256::from(501),
+            gasLimit: U256::from(gas_limit),
+            gasPrice: U256::from(gas_price),
+            value: U256::ZERO,
+            bundleHash: B256::ZERO,
+            feesBundleHash: B256::ZERO,
+            destinationPaymaster: Address::ZERO,
+            destinationPaymasterInput: Bytes::new(),
+        }
+    }
 
-            let existing_source_token = contract
-                .remoteAddresses(source_chain.chain_id.try_into().unwrap())
-                .call()
-                .await
-                .unwrap()
-                ._0;
+    fn fee(gas_limit: u64, max_fee_per_gas: u64, gas_per_pubdata_limit: u64) -> FeeEstimate {
+        FeeEstimate {
+            gas_limit: U256::from(gas_limit),
+            max_fee_per_gas: U256::from(max_fee_per_gas),
+            gas_per_pubdata_limit: U256::from(gas_per_pubdata_limit),
+        }
+    }
 
-            let existing_nominator = contract
-                .ratioNominator(source_chain.chain_id.try_into().unwrap())
-                .call()
-                .await
-                .unwrap()
-                ._0;
+    #[test]
+    fn underpriced_reason() {
+        let tx = interop_tx(1_000_000, 100);
+        // Exactly the estimate is enough.
+        assert_eq!(
+            fee(1_000_000, 100, INTEROP_GAS_PER_PUBDATA).underpriced_reason(&tx),
+            None
+        );
+        assert_eq!(fee(500_000, 50, 800).underpriced_reason(&tx), None);
 
-            let existing_denominator = contract
-                .ratioDenominator(source_chain.chain_id.try_into().unwrap())
-                .call()
-                .await
-                .unwrap()
-                ._0;
+        assert_eq!(
+            fee(1_000_001, 100, 800).underpriced_reason(&tx),
+            Some("gas limit 1000000 is below the estimated 1000001".to_string())
+        );
+        assert_eq!(
+            fee(1_000_000, 101, 800).underpriced_reason(&tx),
+            Some("gas price 100 is below the current 101".to_string())
+        );
+        assert_eq!(
+            fee(1_000_000, 100, INTEROP_GAS_PER_PUBDATA + 1).underpriced_reason(&tx),
+            Some(
+                "chain requires 50001 gas per pubdata, interop transactions use 50000".to_string()
+            )
+        );
+        // Gas limit is reported first.
+        assert_eq!(
+            fee(2_000_000, 200, 800).underpriced_reason(&tx),
+            Some("gas limit 1000000 is below the estimated 2000000".to_string())
+        );
+    }
 
-            if source_chain_token != existing_source_token
-                || existing_nominator != destination_chain.base_token_price.try_into().unwrap()
-                || existing_denominator != source_chain.base_token_price.try_into().unwrap()
-            {
-                let tx_hash = contract
-                    .addOtherBridge(
-                        source_chain.chain_id.try_into().unwrap(),
-                        source_chain_token,
-                        destination_chain.base_token_price.try_into().unwrap(),
-                        source_chain.base_token_price.try_into().unwrap(),
-                    )
-                    .send()
-                    .await
-                    .unwrap()
-                    .watch()
-                    .await
-                    .unwrap();
+    fn log(block: u64, block_hash: u8, msg_hash: u8) -> Log {
+        Log {
+            inner: alloy::primitives::Log::new_unchecked(
+                Address::ZERO,
+                vec![
+                    InteropCenter::InteropMessageSent::SIGNATURE_HASH,
+                    B256::repeat_byte(msg_hash),
+                ],
+                Bytes::new(),
+            ),
+            block_hash: Some(B256::repeat_byte(block_hash)),
+            block_number: Some(block),
+            ..Default::default()
+        }
+    }
 
-                println!(
-                    "Token Trust from {} to {} tx {:?}",
-                    source_chain.chain_id, destination_chain.chain_id, tx_hash
-                );
-            } else {
-                println!(
-                    "Token Trust from {} to {} -- SKIP",
-                    source_chain.chain_id, destination_chain.chain_id
-                );
-            }
+    #[test]
+    fn find_reorg_unchanged() {
+        let logs = vec![log(10, 1, 1), log(10, 1, 2), log(12, 2, 3)];
+        assert_eq!(
+            find_reorg(
+                &group_logs_by_block(logs.clone()),
+                &group_logs_by_block(logs)
+            ),
+            None
+        );
+        assert_eq!(find_reorg(&BTreeMap::new(), &BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn find_reorg_changed() {
+        let processed = group_logs_by_block(vec![log(10, 1, 1), log(12, 2, 2), log(14, 3, 3)]);
+
+        // Same messages, but block 12 has a different hash.
+        let current = group_logs_by_block(vec![log(10, 1, 1), log(12, 9, 2), log(14, 3, 3)]);
+        assert_eq!(find_reorg(&processed, &current), Some(12));
+
+        // Message from block 12 is gone.
+        let current = group_logs_by_block(vec![log(10, 1, 1), log(14, 3, 3)]);
+        assert_eq!(find_reorg(&processed, &current), Some(12));
+
+        // New message in a block without the processed ones.
+        let current = group_logs_by_block(vec![
+            log(10, 1, 1),
+            log(11, 4, 4),
+            log(12, 2, 2),
+            log(14, 3, 3),
+        ]);
+        assert_eq!(find_reorg(&processed, &current), Some(11));
+
+        // Message moved to a later block - the earlier block is reported.
+        let current = group_logs_by_block(vec![log(10, 1, 1), log(13, 5, 2), log(14, 3, 3)]);
+        assert_eq!(find_reorg(&processed, &current), Some(12));
+    }
+
+    const INTEROP_CENTER: Address = Address::repeat_byte(0xCC);
+
+    fn message(data: Vec<u8>, sender: Address, source_chain: u64) -> InteropCenter::InteropMessage {
+        InteropCenter::InteropMessage {
+            data: data.into(),
+            sender,
+            sourceChainId: U256::from(source_chain),
+            messageNum: U256::from(7),
         }
     }
 
-    let shared_map: Arc<Mutex<HashMap<FixedBytes<32>, InteropMessageParsed>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    // Type C message carrying the transaction.
+    fn type_c_message(tx: &InteropCenter::InteropTransaction) -> InteropCenter::InteropMessage {
+        let mut data = vec![2];
+        data.extend(InteropCenter::InteropTransaction::abi_encode(tx));
+        message(data, INTEROP_CENTER, 500)
+    }
 
-    let streaming = cli.streaming;
-
-    let handles: Vec<_> = providers_map
-        .clone()
-        .iter()
-        .map(|(_, entry)| {
-            let entry2 = Arc::clone(&entry);
-            println!("Starting the spawn..");
-            let shared_map = shared_map.clone();
-            let providers_map = providers_map.clone();
-            let handle = tokio::task::spawn(async move {
-                let shared_map = shared_map.clone();
-                let chain_id = entry2.chain_id;
-                entry2
-                    .listen_on_interop_messages(streaming, |log| {
-                        let shared_map = shared_map.clone();
-                        let providers_map = providers_map.clone();
-                        let chain_id = chain_id.clone();
-                        async move {
-                            let msg = InteropMessageParsed::from_log(&log, chain_id);
+    fn sent_log(msg_hash: B256, sender: Address, payload: Vec<u8>) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: INTEROP_CENTER,
+                data: InteropCenter::InteropMessageSent {
+                    msgHash: msg_hash,
+                    sender,
+                    payload: payload.into(),
+                }
+                .encode_log_data(),
+            },
+            block_number: Some(10),
+            ..Default::default()
+        }
+    }
 
-                            println!(
-                                "Got msg from chain: {:?} id:{} hash: {:?} ",
-                                msg.chain_id, msg.interop_message.messageNum, msg.msg_hash
-                            );
+    fn message_log(message: &InteropCenter::InteropMessage) -> Log {
+        let payload = InteropCenter::InteropMessage::abi_encode(message);
+        sent_log(keccak256(&payload), message.sender, payload)
+    }
+
+    #[test]
+    fn from_log_type_c() {
+        let msg = InteropMessageParsed::from_log(
+            &message_log(&type_c_message(&interop_tx(1_000, 10))),
+            500,
+        )
+        .unwrap();
+        assert!(msg.is_type_c());
+        assert_eq!(msg.chain_id, 500);
+        assert_eq!(msg.block_number, Some(10));
+        assert_eq!(msg.status, MessageStatus::Pending);
+        let interop_tx = msg.interop_transaction.unwrap();
+        assert_eq!(interop_tx.destinationChain, U256::from(501));
+        assert_eq!(interop_tx.gasLimit, U256::from(1_000));
+
+        // Other messages don't carry a transaction.
+        let sender = Address::repeat_byte(0x11);
+        let msg =
+            InteropMessageParsed::from_log(&message_log(&message(vec![2, 0xff], sender, 500)), 500)
+                .unwrap();
+        assert!(!msg.is_type_c());
+        assert!(msg.interop_transaction.is_none());
+    }
 
-                            handle_type_a_message(&msg, &providers_map).await;
+    #[test]
+    fn from_log_rejects_hash_mismatch() {
+        let payload =
+            InteropCenter::InteropMessage::abi_encode(&type_c_message(&interop_tx(1_000, 10)));
+        let log = sent_log(B256::repeat_byte(1), INTEROP_CENTER, payload.clone());
+        match InteropMessageParsed::from_log(&log, 500).unwrap_err() {
+            InvalidLogError::HashMismatch { indexed, computed } => {
+                assert_eq!(indexed, B256::repeat_byte(1));
+                assert_eq!(computed, keccak256(&payload));
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
 
-                            if msg.is_type_c() {
-                                handle_type_c_message(&msg, &providers_map, shared_map.clone())
-                                    .await;
-                            }
-                            let mut map = shared_map.lock().await;
+    #[test]
+    fn from_log_rejects_sender_mismatch() {
+        let payload =
+            InteropCenter::InteropMessage::abi_encode(&type_c_message(&interop_tx(1_000, 10)));
+        let sender = Address::repeat_byte(0x11);
+        let log = sent_log(keccak256(&payload), sender, payload);
+        match InteropMessageParsed::from_log(&log, 500).unwrap_err() {
+            InvalidLogError::SenderMismatch { indexed, message } => {
+                assert_eq!(indexed, sender);
+                assert_eq!(message, INTEROP_CENTER);
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
 
-                            map.insert(msg.msg_hash, msg);
-                        }
-                    })
-                    .await;
-            });
-            handle
-        })
-        .collect();
+    #[test]
+    fn from_log_rejects_chain_mismatch() {
+        let log = message_log(&type_c_message(&interop_tx(1_000, 10)));
+        match InteropMessageParsed::from_log(&log, 501).unwrap_err() {
+            InvalidLogError::ChainMismatch { message, actual } => {
+                assert_eq!(message, U256::from(500));
+                assert_eq!(actual, 501);
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
 
-    futures::future::join_all(handles).await;
+    #[test]
+    fn from_log_rejects_malformed_payload() {
+        let payload = vec![0xff; 10];
+        let log = sent_log(keccak256(&payload), INTEROP_CENTER, payload);
+        assert!(matches!(
+            InteropMessageParsed::from_log(&log, 500),
+            Err(InvalidLogError::Payload(_))
+        ));
 
-    // We have to support 2 things:
-    // * for each 'interop message' - 'deliver' it to all the other locations
-    // * for each 'type C' message - detect, create a payload and send.
+        // Type C prefix, but no transaction behind it.
+        let log = message_log(&message(vec![2, 0xff], INTEROP_CENTER, 500));
+        assert!(matches!(
+            InteropMessageParsed::from_log(&log, 500),
+            Err(InvalidLogError::Transaction(_))
+        ));
 
-    Ok(())
+        let mut tx = interop_tx(1_000, 10);
+        tx.destinationChain = U256::MAX;
+        let log = message_log(&type_c_message(&tx));
+        match InteropMessageParsed::from_log(&log, 500).unwrap_err() {
+            InvalidLogError::DestinationChain(chain) => assert_eq!(chain, U256::MAX),
+            error => panic!("unexpected error: {}", error),
+        }
+    }
 }
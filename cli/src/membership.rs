@@ -0,0 +1,109 @@
+use crate::{admin::AddChainRequest, policy::TrustPolicy, setup, InteropChain};
+use std::{collections::HashMap, sync::Arc};
+
+// Sends the request to the admin API of the running relayer.
+async fn notify_relayer(request: reqwest::RequestBuilder) -> anyhow::Result<()> {
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    anyhow::ensure!(
+        status.is_success(),
+        "Relayer admin API returned {}: {}",
+        status,
+        body
+    );
+    println!("Relayer updated: {}", body);
+    Ok(())
+}
+
+// Wires the new chain with the configured ones (in both directions) - pairs between the
+// configured chains are left as they are.
+pub async fn add_chain(
+    mut providers_map: HashMap<u64, Arc<InteropChain>>,
+    new_chain: Arc<InteropChain>,
+    policy: &TrustPolicy,
+    request: AddChainRequest,
+    apply_plan: bool,
+    relayer_admin: Option<&str>,
+) -> anyhow::Result<()> {
+    let new_chain_id = new_chain.chain_id;
+    anyhow::ensure!(
+        providers_map.insert(new_chain_id, new_chain).is_none(),
+        "Chain {} is already configured",
+        new_chain_id
+    );
+
+    policy.check(&providers_map);
+
+    let edges: Vec<_> = policy
+        .edges(&providers_map)
+        .into_iter()
+        .filter(|edge| edge.source_chain == new_chain_id || edge.destination_chain == new_chain_id)
+        .collect();
+    let revoked: Vec<_> = policy
+        .revoked(&providers_map)
+        .into_iter()
+        .filter(|(source_chain, destination_chain)| {
+            *source_chain == new_chain_id || *destination_chain == new_chain_id
+        })
+        .collect();
+    let plan = setup::compute_plan(&providers_map, &edges, &revoked).await;
+    plan.print();
+    if !plan.is_empty() {
+        if !apply_plan {
+            println!("Run with --apply to apply the plan above.");
+            return Ok(());
+        }
+        anyhow::ensure!(
+            setup::apply_plan(&providers_map, &plan).await,
+            "Chain {} is not wired after applying the plan",
+            new_chain_id
+        );
+    }
+    println!("Chain {} is wired with the network.", new_chain_id);
+
+    // The relayer starts only after the trust is in place - otherwise its transactions would fail.
+    if let Some(relayer_admin) = relayer_admin {
+        let request = reqwest::Client::new()
+            .post(format!("{}/admin/chains", relayer_admin))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&request)?);
+        notify_relayer(request).await?;
+    }
+    Ok(())
+}
+
+// Clears the entries about the departing chain on all the configured ones.
+pub async fn remove_chain(
+    mut providers_map: HashMap<u64, Arc<InteropChain>>,
+    removed_chain: u64,
+    apply_plan: bool,
+    relayer_admin: Option<&str>,
+) -> anyhow::Result<()> {
+    // The departing chain itself is left as it is (it might not even be reachable anymore).
+    providers_map.remove(&removed_chain);
+
+    let plan = setup::compute_removal_plan(&providers_map, removed_chain).await;
+    plan.print();
+    if !plan.is_empty() && !apply_plan {
+        println!("Run with --apply to apply the plan above.");
+        return Ok(());
+    }
+
+    // Stop relaying first - so that no new messages from the departing chain fail on the trust checks.
+    if let Some(relayer_admin) = relayer_admin {
+        let request = reqwest::Client::new()
+            .delete(format!("{}/admin/chains/{}", relayer_admin, removed_chain));
+        notify_relayer(request).await?;
+    }
+
+    if !plan.is_empty() {
+        anyhow::ensure!(
+            setup::apply_plan(&providers_map, &plan).await,
+            "Chain {} is still referenced after applying the plan",
+            removed_chain
+        );
+    }
+    println!("Chain {} removed from the network.", removed_chain);
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use crate::{
+    revert::RevertReason,
+    InteropCenter::{InteropMessage, InteropTransaction},
+};
+use alloy::{
+    primitives::{keccak256, Address, Bytes, U256},
+    sol,
+    sol_types::{SolCall, SolValue},
+};
+
+// Standard paymaster flows (IPaymasterFlow from era-contracts).
+sol! {
+    interface IPaymasterFlow {
+        function general(bytes calldata input) external;
+        function approvalBased(
+            address _token,
+            uint256 _minAllowance,
+            bytes calldata _innerInput
+        ) external;
+    }
+}
+
+// What the user asked the destination paymaster for (destinationPaymasterInput).
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaymasterFlow {
+    // No input.
+    Empty,
+    General(Bytes),
+    ApprovalBased {
+        token: Address,
+        min_allowance: U256,
+        inner_input: Bytes,
+    },
+}
+
+impl PaymasterFlow {
+    pub fn decode(input: &[u8]) -> Result<Self, RevertReason> {
+        if input.is_empty() {
+            return Ok(PaymasterFlow::Empty);
+        }
+        if let Ok(call) = IPaymasterFlow::generalCall::abi_decode(input, true) {
+            return Ok(PaymasterFlow::General(call.input));
+        }
+        if let Ok(call) = IPaymasterFlow::approvalBasedCall::abi_decode(input, true) {
+            return Ok(PaymasterFlow::ApprovalBased {
+                token: call._token,
+                min_allowance: call._minAllowance,
+                inner_input: call._innerInput,
+            });
+        }
+        Err(RevertReason::UnsupportedPaymasterFlow)
+    }
+}
+
+// Paymaster input of the type C transaction. With a fee bundle it is what CrossPaymaster expects -
+// abi.encode(feeBundle, destinationPaymasterInput), otherwise the user input goes as is.
+pub fn compose_paymaster_input(
+    interop_tx: &InteropTransaction,
+    fee_message: Option<&InteropMessage>,
+) -> Vec<u8> {
+    match fee_message {
+        Some(fee_message) => (
+            fee_message.clone(),
+            interop_tx.destinationPaymasterInput.clone(),
+        )
+            .abi_encode_params(),
+        None => interop_tx.destinationPaymasterInput.to_vec(),
+    }
+}
+
+// Checks the composed input the same way InteropCenter and CrossPaymaster will.
+// Paymaster token is known only for CrossPaymaster (the preferred paymaster) - other paymasters
+// get just the flow format checked.
+pub fn validate_paymaster_input(
+    input: &[u8],
+    interop_tx: &InteropTransaction,
+    paymaster_token: Option<Address>,
+) -> Result<PaymasterFlow, RevertReason> {
+    let user_input = if interop_tx.feesBundleHash.is_zero() {
+        Bytes::copy_from_slice(input)
+    } else {
+        let (fee_message, user_input) = <(InteropMessage, Bytes)>::abi_decode_params(input, true)
+            .map_err(|_| RevertReason::FeesBundleHashMismatch)?;
+        if keccak256(fee_message.abi_encode()) != interop_tx.feesBundleHash {
+            return Err(RevertReason::FeesBundleHashMismatch);
+        }
+        user_input
+    };
+
+    let flow = PaymasterFlow::decode(&user_input)?;
+    if let (
+        PaymasterFlow::ApprovalBased {
+            token,
+            min_allowance,
+            ..
+        },
+        Some(paymaster_token),
+    ) = (&flow, paymaster_token)
+    {
+        if *token != paymaster_token {
+            return Err(RevertReason::WrongPaymasterFlowToken);
+        }
+        // CrossPaymaster always takes max fee * gas limit.
+        if *min_allowance < interop_tx.gasPrice.saturating_mul(interop_tx.gasLimit) {
+            return Err(RevertReason::PaymasterFlowAllowanceTooLow);
+        }
+    }
+    Ok(flow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::B256;
+
+    const TOKEN: Address = Address::repeat_byte(0x77);
+
+    fn interop_tx(paymaster_input: Vec<u8>, fees_bundle_hash: B256) -> InteropTransaction {
+        InteropTransaction {
+            sourceChainSender: Address::repeat_byte(1),
+            destinationChain: U256::from(501),
+            gasLimit: U256::from(1_000),
+            gasPrice: U256::from(10),
+            value: U256::ZERO,
+            bundleHash: B256::repeat_byte(2),
+            feesBundleHash: fees_bundle_hash,
+            destinationPaymaster: Address::repeat_byte(3),
+            destinationPaymasterInput: paymaster_input.into(),
+        }
+    }
+
+    fn fee_message(message_num: u64) -> InteropMessage {
+        InteropMessage {
+            data: vec![1, 2, 3].into(),
+            sender: Address::repeat_byte(4),
+            sourceChainId: U256::from(500),
+            messageNum: U256::from(message_num),
+        }
+    }
+
+    fn approval_based(token: Address, min_allowance: u64) -> Vec<u8> {
+        IPaymasterFlow::approvalBasedCall {
+            _token: token,
+            _minAllowance: U256::from(min_allowance),
+            _innerInput: Bytes::from(vec![9]),
+        }
+        .abi_encode()
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(PaymasterFlow::decode(&[]), Ok(PaymasterFlow::Empty));
+
+        let general = IPaymasterFlow::generalCall {
+            input: Bytes::from(vec![1, 2]),
+        }
+        .abi_encode();
+        assert_eq!(
+            PaymasterFlow::decode(&general),
+            Ok(PaymasterFlow::General(Bytes::from(vec![1, 2])))
+        );
+
+        assert_eq!(
+            PaymasterFlow::decode(&approval_based(TOKEN, 10_000)),
+            Ok(PaymasterFlow::ApprovalBased {
+                token: TOKEN,
+                min_allowance: U256::from(10_000),
+                inner_input: Bytes::from(vec![9]),
+            })
+        );
+    }
+
+    #[test]
+    fn decode_unsupported() {
+        assert_eq!(
+            PaymasterFlow::decode(&[1, 2, 3]),
+            Err(RevertReason::UnsupportedPaymasterFlow)
+        );
+        // Right arguments behind an unknown selector.
+        let mut input = approval_based(TOKEN, 10_000);
+        input[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            PaymasterFlow::decode(&input),
+            Err(RevertReason::UnsupportedPaymasterFlow)
+        );
+        // Truncated arguments.
+        let input = approval_based(TOKEN, 10_000);
+        assert_eq!(
+            PaymasterFlow::decode(&input[..36]),
+            Err(RevertReason::UnsupportedPaymasterFlow)
+        );
+    }
+
+    #[test]
+    fn without_fee_bundle() {
+        let user_input = approval_based(TOKEN, 10_000);
+        let tx = interop_tx(user_input.clone(), B256::ZERO);
+        let input = compose_paymaster_input(&tx, None);
+        assert_eq!(input, user_input);
+        assert!(matches!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Ok(PaymasterFlow::ApprovalBased { .. })
+        ));
+    }
+
+    #[test]
+    fn with_fee_bundle() {
+        let fee = fee_message(1);
+        let tx = interop_tx(approval_based(TOKEN, 10_000), keccak256(fee.abi_encode()));
+        let input = compose_paymaster_input(&tx, Some(&fee));
+        let (decoded_fee, user_input) =
+            <(InteropMessage, Bytes)>::abi_decode_params(&input, true).unwrap();
+        assert_eq!(decoded_fee.messageNum, fee.messageNum);
+        assert_eq!(user_input, tx.destinationPaymasterInput);
+        assert_eq!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Ok(PaymasterFlow::ApprovalBased {
+                token: TOKEN,
+                min_allowance: U256::from(10_000),
+                inner_input: Bytes::from(vec![9]),
+            })
+        );
+    }
+
+    #[test]
+    fn fee_bundle_mismatch() {
+        let tx = interop_tx(
+            approval_based(TOKEN, 10_000),
+            keccak256(fee_message(1).abi_encode()),
+        );
+        // Some other fee message.
+        let input = compose_paymaster_input(&tx, Some(&fee_message(2)));
+        assert_eq!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Err(RevertReason::FeesBundleHashMismatch)
+        );
+        // Not composed with the fee message at all.
+        let input = compose_paymaster_input(&tx, None);
+        assert_eq!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Err(RevertReason::FeesBundleHashMismatch)
+        );
+    }
+
+    #[test]
+    fn wrong_token() {
+        let tx = interop_tx(
+            approval_based(Address::repeat_byte(0x66), 10_000),
+            B256::ZERO,
+        );
+        let input = compose_paymaster_input(&tx, None);
+        assert_eq!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Err(RevertReason::WrongPaymasterFlowToken)
+        );
+        // Token is not checked for the other paymasters.
+        assert!(validate_paymaster_input(&input, &tx, None).is_ok());
+    }
+
+    #[test]
+    fn allowance_too_low() {
+        // Gas limit 1000 * gas price 10.
+        let tx = interop_tx(approval_based(TOKEN, 9_999), B256::ZERO);
+        let input = compose_paymaster_input(&tx, None);
+        assert_eq!(
+            validate_paymaster_input(&input, &tx, Some(TOKEN)),
+            Err(RevertReason::PaymasterFlowAllowanceTooLow)
+        );
+        assert!(validate_paymaster_input(&input, &tx, None).is_ok());
+    }
+}
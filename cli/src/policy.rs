@@ -0,0 +1,211 @@
+use crate::{
+    setup::{full_mesh, Edge},
+    InteropChain,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+// Which chains accept interop messages from which.
+// Setup wires only the allowed pairs (and clears the other ones), and the relayer doesn't forward
+// messages along the pairs that are not allowed.
+#[derive(Debug, Clone)]
+pub enum TrustPolicy {
+    // Every chain accepts messages from every chain (including itself).
+    FullMesh,
+    // Destination chain -> source chains that it accepts messages from.
+    // Every configured chain accepts its own messages, whether it is listed or not.
+    AcceptFrom(BTreeMap<u64, BTreeSet<u64>>),
+}
+
+// Policy file, for example: {"accept_from": {"501": [500]}}
+#[derive(Deserialize)]
+struct TrustPolicyFile {
+    accept_from: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl TrustPolicy {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file: TrustPolicyFile = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(TrustPolicy::AcceptFrom(file.accept_from))
+    }
+
+    pub fn allows(&self, source_chain: u64, destination_chain: u64) -> bool {
+        match self {
+            TrustPolicy::FullMesh => true,
+            TrustPolicy::AcceptFrom(accept_from) => {
+                source_chain == destination_chain
+                    || accept_from
+                        .get(&destination_chain)
+                        .is_some_and(|sources| sources.contains(&source_chain))
+            }
+        }
+    }
+
+    // Pairs of the configured chains that should be wired.
+    pub fn edges(&self, providers_map: &HashMap<u64, Arc<InteropChain>>) -> Vec<Edge> {
+        self.allowed_edges(full_mesh(providers_map))
+    }
+
+    fn allowed_edges(&self, edges: Vec<Edge>) -> Vec<Edge> {
+        edges
+            .into_iter()
+            .filter(|edge| self.allows(edge.source_chain, edge.destination_chain))
+            .collect()
+    }
+
+    // (source chain, destination chain) pairs of the configured chains that must not be wired.
+    pub fn revoked(&self, providers_map: &HashMap<u64, Arc<InteropChain>>) -> Vec<(u64, u64)> {
+        self.revoked_between(providers_map.keys().copied())
+    }
+
+    fn revoked_between(&self, chain_ids: impl Iterator<Item = u64> + Clone) -> Vec<(u64, u64)> {
+        let mut revoked = vec![];
+        for source_chain in chain_ids.clone() {
+            for destination_chain in chain_ids.clone() {
+                if !self.allows(source_chain, destination_chain) {
+                    revoked.push((source_chain, destination_chain));
+                }
+            }
+        }
+        revoked
+    }
+
+    // Warns about the chains that the policy and the configuration don't agree on.
+    pub fn check(&self, providers_map: &HashMap<u64, Arc<InteropChain>>) {
+        let TrustPolicy::AcceptFrom(accept_from) = self else {
+            return;
+        };
+        let mentioned: BTreeSet<u64> = accept_from
+            .iter()
+            .flat_map(|(destination_chain, sources)| {
+                sources.iter().chain(std::iter::once(destination_chain))
+            })
+            .copied()
+            .collect();
+        for chain_id in mentioned {
+            if !providers_map.contains_key(&chain_id) {
+                println!(
+                    "!! Trust policy mentions chain {}, which is not configured",
+                    chain_id
+                );
+            }
+        }
+        for chain_id in providers_map.keys() {
+            if !accept_from.contains_key(chain_id) {
+                println!(
+                    "!! Chain {} is not in the trust policy - it accepts only its own messages",
+                    chain_id
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+
+    const CHAINS: [u64; 3] = [500, 501, 502];
+
+    // 501 accepts messages from 500, 502 from 500 and 501 - nobody accepts messages from 502.
+    fn accept_from() -> TrustPolicy {
+        TrustPolicy::AcceptFrom(BTreeMap::from([
+            (501, BTreeSet::from([500])),
+            (502, BTreeSet::from([500, 501])),
+        ]))
+    }
+
+    fn mesh() -> Vec<Edge> {
+        let mut edges = vec![];
+        for source_chain in CHAINS {
+            for destination_chain in CHAINS {
+                edges.push(Edge {
+                    source_chain,
+                    destination_chain,
+                    ratio_nominator: U256::from(1),
+                    ratio_denominator: U256::from(1),
+                });
+            }
+        }
+        edges
+    }
+
+    fn pairs(edges: Vec<Edge>) -> Vec<(u64, u64)> {
+        edges
+            .into_iter()
+            .map(|edge| (edge.source_chain, edge.destination_chain))
+            .collect()
+    }
+
+    #[test]
+    fn full_mesh_allows_everything() {
+        let policy = TrustPolicy::FullMesh;
+        for source_chain in CHAINS {
+            for destination_chain in CHAINS {
+                assert!(policy.allows(source_chain, destination_chain));
+            }
+        }
+        assert_eq!(policy.allowed_edges(mesh()), mesh());
+        assert_eq!(policy.revoked_between(CHAINS.into_iter()), vec![]);
+    }
+
+    #[test]
+    fn accept_from_allows() {
+        let policy = accept_from();
+        assert!(policy.allows(500, 501));
+        assert!(policy.allows(500, 502));
+        assert!(policy.allows(501, 502));
+        assert!(!policy.allows(501, 500));
+        assert!(!policy.allows(502, 500));
+        assert!(!policy.allows(502, 501));
+        // Every chain accepts its own messages - also the ones that are not listed.
+        assert!(policy.allows(500, 500));
+        assert!(policy.allows(502, 502));
+        assert!(policy.allows(503, 503));
+        // Unknown chains are not accepted anywhere.
+        assert!(!policy.allows(503, 501));
+        assert!(!policy.allows(500, 503));
+    }
+
+    #[test]
+    fn accept_from_edges() {
+        assert_eq!(
+            pairs(accept_from().allowed_edges(mesh())),
+            vec![
+                (500, 500),
+                (500, 501),
+                (500, 502),
+                (501, 501),
+                (501, 502),
+                (502, 502)
+            ]
+        );
+    }
+
+    #[test]
+    fn accept_from_revoked() {
+        assert_eq!(
+            accept_from().revoked_between(CHAINS.into_iter()),
+            vec![(501, 500), (502, 500), (502, 501)]
+        );
+        // Chain that is not configured is not revoked.
+        assert_eq!(
+            accept_from().revoked_between([500, 501].into_iter()),
+            vec![(501, 500)]
+        );
+    }
+
+    #[test]
+    fn edges_and_revoked_cover_all_pairs() {
+        let policy = accept_from();
+        let mut all = pairs(policy.allowed_edges(mesh()));
+        all.extend(policy.revoked_between(CHAINS.into_iter()));
+        all.sort();
+        assert_eq!(all, pairs(mesh()));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    setup::{apply_change, read_bridge_info, BridgeInfo, ChangeKind, SetupChange},
+    InteropChain, Relayer,
+};
+use alloy::primitives::U256;
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Mutex;
+
+// Prices of the base tokens (10^18) in cents, per chain id - e.g. {"500": 200000, "501": 100}.
+// Both the price file and the HTTP endpoint return this format.
+pub type Prices = BTreeMap<u64, u64>;
+
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> String;
+
+    // Current prices - chains that are missing keep their previous price.
+    async fn prices(&self) -> anyhow::Result<Prices>;
+}
+
+// Prices from the command line (--base-token-price) - never change.
+pub struct StaticPrices(pub Prices);
+
+#[async_trait]
+impl PriceSource for StaticPrices {
+    fn name(&self) -> String {
+        "static".to_string()
+    }
+
+    async fn prices(&self) -> anyhow::Result<Prices> {
+        Ok(self.0.clone())
+    }
+}
+
+// Prices from a JSON file - it is re-read whenever it changes.
+pub struct FilePrices {
+    path: PathBuf,
+    // Modification time and the content of the file, when it was last read.
+    cached: Mutex<Option<(SystemTime, Prices)>>,
+}
+
+impl FilePrices {
+    pub fn new(path: PathBuf) -> Self {
+        FilePrices {
+            path,
+            cached: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for FilePrices {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    async fn prices(&self) -> anyhow::Result<Prices> {
+        let modified = std::fs::metadata(&self.path)?.modified()?;
+        let mut cached = self.cached.lock().await;
+        if let Some((cached_modified, prices)) = cached.as_ref() {
+            if *cached_modified == modified {
+                return Ok(prices.clone());
+            }
+        }
+        let prices: Prices = serde_json::from_str(&std::fs::read_to_string(&self.path)?)?;
+        println!("Loaded prices from {}: {:?}", self.path.display(), prices);
+        *cached = Some((modified, prices.clone()));
+        Ok(prices)
+    }
+}
+
+// Prices from an HTTP endpoint, that returns them as JSON.
+pub struct HttpPrices {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpPrices {
+    pub fn new(url: String) -> Self {
+        HttpPrices {
+            url,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for HttpPrices {
+    fn name(&self) -> String {
+        format!("http {}", self.url)
+    }
+
+    async fn prices(&self) -> anyhow::Result<Prices> {
+        let response = self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+}
+
+pub struct PriceUpdateConfig {
+    pub interval: Duration,
+    // Ratios are updated on chain only when they are off by more than this (in basis points).
+    pub max_drift_bps: u64,
+    // Without it, the drift is only reported.
+    pub apply: bool,
+}
+
+// Difference between the current and the desired ratio, relative to the desired one, in basis points.
+fn ratio_drift_bps(current: &BridgeInfo, nominator: U256, denominator: U256) -> U256 {
+    if current.ratio_denominator.is_zero() || denominator.is_zero() {
+        return U256::MAX;
+    }
+    // current_nom / current_den vs nom / den - multiplied by both denominators.
+    let current_value = current.ratio_nominator * denominator;
+    let desired_value = nominator * current.ratio_denominator;
+    if desired_value.is_zero() {
+        return U256::MAX;
+    }
+    let difference = if current_value > desired_value {
+        current_value - desired_value
+    } else {
+        desired_value - current_value
+    };
+    difference * U256::from(10_000) / desired_value
+}
+
+// Updates the base token prices of the chains from the source.
+async fn refresh_prices(source: &dyn PriceSource, chains: &HashMap<u64, Arc<InteropChain>>) {
+    let prices = match source.prices().await {
+        Ok(prices) => prices,
+        Err(error) => {
+            println!("!! Failed to get prices from {}: {}", source.name(), error);
+            return;
+        }
+    };
+    for (chain_id, chain) in chains {
+        match prices.get(chain_id) {
+            Some(0) => println!("!! Ignoring zero price for chain {}", chain_id),
+            Some(price) => {
+                let previous = chain.base_token_price.swap(*price, Ordering::Relaxed);
+                if previous != *price {
+                    println!(
+                        "Base token price of chain {} changed: {} -> {} cents",
+                        chain_id, previous, price
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+// Checks the paymaster token ratios of all the wired pairs, and updates the ones that drifted.
+async fn update_ratios(relayer: &Relayer, config: &PriceUpdateConfig) {
+    let chains = relayer.chains().await;
+    // Pairs whose state can't be read are skipped - and checked again on the next run.
+    let mut tokens = HashMap::new();
+    for (chain_id, chain) in &chains {
+        match chain.get_paymaster_basic_token().await {
+            Ok(token) => {
+                tokens.insert(*chain_id, token);
+            }
+            Err(error) => println!(
+                "!! Can't read the paymaster token of chain {}: {}",
+                chain_id, error
+            ),
+        }
+    }
+
+    for edge in relayer.policy.edges(&chains) {
+        let (Some(&token), Some(&source_token)) = (
+            tokens.get(&edge.destination_chain),
+            tokens.get(&edge.source_chain),
+        ) else {
+            continue;
+        };
+        let destination_chain = &chains[&edge.destination_chain];
+        let current = match read_bridge_info(destination_chain, token, edge.source_chain).await {
+            Ok(current) => current,
+            Err(error) => {
+                println!(
+                    "!! Can't read the paymaster token bridge on chain {} (source {}): {}",
+                    edge.destination_chain, edge.source_chain, error
+                );
+                continue;
+            }
+        };
+        // Not wired yet - that is up to the setup.
+        if current.remote_address != source_token {
+            continue;
+        }
+        let drift = ratio_drift_bps(&current, edge.ratio_nominator, edge.ratio_denominator);
+        if drift <= U256::from(config.max_drift_bps) {
+            continue;
+        }
+        let change = SetupChange {
+            source_chain: edge.source_chain,
+            destination_chain: edge.destination_chain,
+            kind: ChangeKind::PaymasterTokenBridge {
+                token,
+                desired: BridgeInfo {
+                    remote_address: current.remote_address,
+                    ratio_nominator: edge.ratio_nominator,
+                    ratio_denominator: edge.ratio_denominator,
+                },
+                current,
+            },
+        };
+        println!(
+            "Paymaster token ratio drifted by {} bps:\n  {}",
+            drift, change
+        );
+        if config.apply {
+            // A failed update is tried again on the next run - it must not stop the price updates.
+            match apply_change(destination_chain, &change).await {
+                Ok(tx_hash) => println!(
+                    "Updated ratio on chain {} (source {}) tx {:?}",
+                    edge.destination_chain, edge.source_chain, tx_hash
+                ),
+                Err(error) => println!(
+                    "!! Updating ratio on chain {} (source {}) failed: {}",
+                    edge.destination_chain, edge.source_chain, error
+                ),
+            }
+        } else {
+            println!("!! Ratio not updated - run with --apply to update the ratios.");
+        }
+    }
+}
+
+// Periodically refreshes the prices and keeps the paymaster token ratios in line with them.
+pub async fn run_price_updates(
+    relayer: Relayer,
+    source: Arc<dyn PriceSource>,
+    config: PriceUpdateConfig,
+) {
+    println!(
+        "Price updates from {} every {:?} (max drift {} bps)",
+        source.name(),
+        config.interval,
+        config.max_drift_bps
+    );
+    let mut shutdown = relayer.shutdown.clone();
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(config.interval) => {},
+            _ = shutdown.changed() => {},
+        }
+        if *shutdown.borrow() {
+            return;
+        }
+        refresh_prices(source.as_ref(), &relayer.chains().await).await;
+        update_ratios(&relayer, &config).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::get, Router};
+
+    // Serves the response on a local port, and returns the price source that reads it.
+    async fn serve(status: StatusCode, body: &'static str) -> HttpPrices {
+        let app = Router::new().route("/prices", get(move || async move { (status, body) }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        HttpPrices::new(format!("http://{}/prices", address))
+    }
+
+    #[tokio::test]
+    async fn http_prices() {
+        let source = serve(StatusCode::OK, r#"{"500": 200000}"#).await;
+        assert_eq!(
+            source.prices().await.unwrap(),
+            Prices::from([(500, 200000)])
+        );
+    }
+
+    #[tokio::test]
+    async fn http_prices_missing_chain() {
+        // Chains that are missing keep their previous price - the response is still valid.
+        let source = serve(StatusCode::OK, r#"{"501": 100}"#).await;
+        let prices = source.prices().await.unwrap();
+        assert_eq!(prices.get(&500), None);
+        assert_eq!(prices.get(&501), Some(&100));
+    }
+
+    #[tokio::test]
+    async fn http_prices_malformed() {
+        for body in [r#"{"500": "#, r#"{"500": -1}"#, r#"{"chain": 100}"#, "[]"] {
+            let source = serve(StatusCode::OK, body).await;
+            assert!(source.prices().await.is_err(), "accepted {}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn http_prices_error_status() {
+        let source = serve(StatusCode::INTERNAL_SERVER_ERROR, r#"{"500": 200000}"#).await;
+        assert!(source.prices().await.is_err());
+    }
+
+    fn bridge(ratio_nominator: u64, ratio_denominator: u64) -> BridgeInfo {
+        BridgeInfo {
+            remote_address: Default::default(),
+            ratio_nominator: U256::from(ratio_nominator),
+            ratio_denominator: U256::from(ratio_denominator),
+        }
+    }
+
+    #[test]
+    fn drift() {
+        // (current ratio, desired ratio, drift in bps)
+        let cases = [
+            ((1, 1), (1, 1), U256::ZERO),
+            // Same ratio, written differently.
+            ((2, 4), (1, 2), U256::ZERO),
+            ((101, 100), (1, 1), U256::from(100)),
+            ((99, 100), (1, 1), U256::from(100)),
+            ((1, 2), (1, 1), U256::from(5_000)),
+            ((2, 1), (1, 1), U256::from(10_000)),
+            ((0, 1), (1, 1), U256::from(10_000)),
+            // Zero ratios can't be compared - always treated as drifted.
+            ((1, 0), (1, 1), U256::MAX),
+            ((1, 1), (1, 0), U256::MAX),
+            ((1, 1), (0, 1), U256::MAX),
+            ((0, 0), (0, 0), U256::MAX),
+        ];
+        for ((current_nominator, current_denominator), (nominator, denominator), expected) in cases
+        {
+            assert_eq!(
+                ratio_drift_bps(
+                    &bridge(current_nominator, current_denominator),
+                    U256::from(nominator),
+                    U256::from(denominator)
+                ),
+                expected,
+                "{}/{} vs {}/{}",
+                current_nominator,
+                current_denominator,
+                nominator,
+                denominator
+            );
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    setup::read_bridge_info, CrossPaymaster, InteropCenter, InteropChain, InteropMessageParsed,
+    PaymasterToken, Relayer,
+};
+use alloy::{
+    network::ReceiptResponse,
+    primitives::{Address, Bytes, U256},
+    providers::Provider,
+    sol_types::SolEvent,
+    transports::http::{Client, Http},
+};
+use alloy_zksync::{network::Zksync, provider::zksync_provider};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::OwnedMutexGuard;
+
+// How long to wait for the token bundle to be forwarded to the destination chain.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct RebalanceConfig {
+    // Paymaster tokens (in cents) that stay with each paymaster.
+    pub token_reserve_cents: u64,
+    // Tokens above the reserve are swept only once they are worth at least this.
+    pub min_sweep_cents: u64,
+}
+
+// What was done with the tokens collected by the paymaster of a single chain.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RebalanceReport {
+    pub chain_id: u64,
+    pub paymaster_tokens: U256,
+    pub swept: U256,
+    // Exchanged back to the base token on this chain (when no other paymaster needed the tokens).
+    pub redeemed: U256,
+    pub bridged_to: Option<u64>,
+    pub bridged: U256,
+    // Arrived on the target chain and given to its paymaster.
+    pub delivered: U256,
+}
+
+fn admin_provider(chain: &InteropChain) -> anyhow::Result<impl Provider<Http<Client>, Zksync>> {
+    Ok(zksync_provider()
+        .with_recommended_fillers()
+        .wallet(chain.admin_wallet.clone())
+        .on_http(chain.rpc().parse()?))
+}
+
+async fn token_balance(
+    chain: &InteropChain,
+    token: Address,
+    owner: Address,
+) -> anyhow::Result<U256> {
+    Ok(PaymasterToken::new(token, chain.provider())
+        .balanceOf(owner)
+        .call()
+        .await?
+        ._0)
+}
+
+// Exchanges the admin tokens back to the base token - as much as the token contract can cover.
+async fn redeem(chain: &InteropChain, token: Address, amount: U256) -> anyhow::Result<U256> {
+    let amount = amount.min(chain.get_balance(token).await?);
+    if amount.is_zero() {
+        return Ok(amount);
+    }
+    let provider = admin_provider(chain)?;
+    let tx_hash = PaymasterToken::new(token, &provider)
+        .redeem(amount)
+        .send()
+        .await?
+        .watch()
+        .await?;
+    println!(
+        "  Redeemed {} paymaster tokens on chain {} with tx {:?}",
+        amount, chain.chain_id, tx_hash
+    );
+    Ok(amount)
+}
+
+// Gives the admin tokens to the paymaster.
+async fn deliver(
+    chain: &InteropChain,
+    token: Address,
+    paymaster: Address,
+    amount: U256,
+) -> anyhow::Result<U256> {
+    if amount.is_zero() {
+        return Ok(amount);
+    }
+    let provider = admin_provider(chain)?;
+    let tx_hash = PaymasterToken::new(token, &provider)
+        .transfer(paymaster, amount)
+        .send()
+        .await?
+        .watch()
+        .await?;
+    println!(
+        "  Gave {} paymaster tokens to paymaster {} on chain {} with tx {:?}",
+        amount, paymaster, chain.chain_id, tx_hash
+    );
+    Ok(amount)
+}
+
+// Sends the admin tokens to the admin on the destination chain, and executes the bundle there
+// (once the relayer forwarded it). Returns how many tokens arrived.
+async fn bridge(
+    source: &InteropChain,
+    source_token: Address,
+    destination: &InteropChain,
+    destination_token: Address,
+    amount: U256,
+) -> anyhow::Result<U256> {
+    let before = token_balance(destination, destination_token, destination.admin_address).await?;
+
+    let provider = admin_provider(source)?;
+    let receipt = PaymasterToken::new(source_token, &provider)
+        .sendToRemoteInNewBundle(
+            U256::from(destination.chain_id),
+            destination.admin_address,
+            amount,
+        )
+        .send()
+        .await?
+        .get_receipt()
+        .await?;
+    let log = receipt
+        .inner
+        .inner
+        .logs()
+        .iter()
+        .find(|log| {
+            log.topics().first() == Some(&InteropCenter::InteropMessageSent::SIGNATURE_HASH)
+        })
+        .ok_or_else(|| {
+            anyhow::anyhow!("No interop message in tx {:?}", receipt.transaction_hash())
+        })?;
+    let msg = InteropMessageParsed::from_log(log, source.chain_id)?;
+    println!(
+        "  Sent {} paymaster tokens from chain {} to chain {} in bundle {:?}",
+        amount, source.chain_id, destination.chain_id, msg.msg_hash
+    );
+
+    // Listener of the source chain forwards the bundle.
+    let started = std::time::Instant::now();
+    while !destination.is_message_received(msg.msg_hash).await? {
+        anyhow::ensure!(
+            started.elapsed() < FORWARD_TIMEOUT,
+            "Bundle {:?} was not forwarded to chain {} in time",
+            msg.msg_hash,
+            destination.chain_id
+        );
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+
+    let provider = admin_provider(destination)?;
+    let tx_hash = InteropCenter::new(destination.interop_address, &provider)
+        .executeInteropBundle(msg.interop_message, Bytes::new())
+        .send()
+        .await?
+        .watch()
+        .await?;
+    println!(
+        "  Executed token bundle on chain {} with tx {:?}",
+        destination.chain_id, tx_hash
+    );
+
+    let after = token_balance(destination, destination_token, destination.admin_address).await?;
+    Ok(after.saturating_sub(before))
+}
+
+// Preferred paymaster of the chain, its token and how many of them it holds.
+async fn paymaster_holdings(chain: &InteropChain) -> anyhow::Result<(Address, Address, U256)> {
+    let paymaster = chain.get_preferred_paymaster().await?;
+    let token = chain.get_paymaster_basic_token().await?;
+    Ok((
+        paymaster,
+        token,
+        token_balance(chain, token, paymaster).await?,
+    ))
+}
+
+// Sweeps the surplus paymaster tokens of the chain: they go to the paymaster that holds the fewest
+// tokens (if any is below the reserve), or are exchanged back to the base token here.
+async fn rebalance_chain(
+    relayer: &Relayer,
+    chain: &Arc<InteropChain>,
+    config: &RebalanceConfig,
+) -> anyhow::Result<RebalanceReport> {
+    let (paymaster, token, holdings) = paymaster_holdings(chain).await?;
+    let mut report = RebalanceReport {
+        chain_id: chain.chain_id,
+        paymaster_tokens: holdings,
+        ..Default::default()
+    };
+
+    let surplus = holdings.saturating_sub(chain.cents_to_tokens(config.token_reserve_cents));
+    if surplus.is_zero() || surplus < chain.cents_to_tokens(config.min_sweep_cents) {
+        return Ok(report);
+    }
+    let provider = admin_provider(chain)?;
+    let tx_hash = CrossPaymaster::new(paymaster, &provider)
+        .withdrawTokens(chain.admin_address, surplus)
+        .send()
+        .await?
+        .watch()
+        .await?;
+    println!(
+        "  Swept {} paymaster tokens from paymaster on chain {} with tx {:?}",
+        surplus, chain.chain_id, tx_hash
+    );
+    report.swept = surplus;
+
+    // Paymaster with the fewest tokens, that this chain can send the tokens to.
+    let mut target: Option<(Arc<InteropChain>, Address, Address, U256)> = None;
+    for (chain_id, other) in relayer.chains().await {
+        if chain_id == chain.chain_id || !relayer.policy.allows(chain.chain_id, chain_id) {
+            continue;
+        }
+        // Chains that can't be read now are not considered - the tokens are already swept.
+        let (other_paymaster, other_token, balance) = match paymaster_holdings(&other).await {
+            Ok(holdings) => holdings,
+            Err(error) => {
+                println!(
+                    "!! Can't read the paymaster tokens on chain {}: {}",
+                    chain_id, error
+                );
+                continue;
+            }
+        };
+        if balance >= other.cents_to_tokens(config.token_reserve_cents) {
+            continue;
+        }
+        // This chain's token must know the token on the other chain.
+        match read_bridge_info(chain, token, chain_id).await {
+            Ok(bridge) if !bridge.remote_address.is_zero() => {}
+            Ok(_) => continue,
+            Err(error) => {
+                println!(
+                    "!! Can't read the paymaster token bridge to chain {} on chain {}: {}",
+                    chain_id, chain.chain_id, error
+                );
+                continue;
+            }
+        }
+        if target
+            .as_ref()
+            .map_or(true, |(_, _, _, lowest_balance)| balance < *lowest_balance)
+        {
+            target = Some((other, other_paymaster, other_token, balance));
+        }
+    }
+
+    match target {
+        Some((target, target_paymaster, target_token, _)) => {
+            let arrived = bridge(chain, token, &target, target_token, surplus).await?;
+            report.bridged_to = Some(target.chain_id);
+            report.bridged = surplus;
+            // Bridged tokens have no base token behind them on the target chain - so they are
+            // not redeemed there, but handed over to the paymaster.
+            report.delivered = deliver(&target, target_token, target_paymaster, arrived).await?;
+        }
+        None => {
+            report.redeemed = redeem(chain, token, surplus).await?;
+            if report.redeemed < surplus {
+                println!(
+                    "!! Only {} of {} swept tokens could be redeemed on chain {} - the rest stays with the admin",
+                    report.redeemed, surplus, chain.chain_id
+                );
+            }
+        }
+    }
+    Ok(report)
+}
+
+// Only one rebalancing runs at a time - the interval task and the admin endpoint would otherwise
+// sweep and bridge the same tokens twice.
+fn try_start(relayer: &Relayer) -> Option<OwnedMutexGuard<()>> {
+    let running = relayer.rebalancing.clone().try_lock_owned().ok();
+    if running.is_none() {
+        println!("Rebalancing is already running - skipped");
+    }
+    running
+}
+
+async fn rebalance_all(relayer: &Relayer, config: &RebalanceConfig) -> Vec<RebalanceReport> {
+    let mut reports = vec![];
+    for chain in relayer.chains().await.values() {
+        match rebalance_chain(relayer, chain, config).await {
+            Ok(report) => reports.push(report),
+            Err(error) => println!(
+                "!! Rebalancing paymaster tokens of chain {} failed: {}",
+                chain.chain_id, error
+            ),
+        }
+    }
+    reports.sort_by_key(|report| report.chain_id);
+    reports
+}
+
+// Returns None, if another rebalancing is in progress.
+pub async fn rebalance(
+    relayer: &Relayer,
+    config: &RebalanceConfig,
+) -> Option<Vec<RebalanceReport>> {
+    let _running = try_start(relayer)?;
+    Some(rebalance_all(relayer, config).await)
+}
+
+// Starts the rebalancing in the background (for the admin API). Returns false, if another one is in progress.
+pub fn spawn_rebalance(relayer: Relayer) -> bool {
+    let Some(running) = try_start(&relayer) else {
+        return false;
+    };
+    relayer.spawn_background({
+        let relayer = relayer.clone();
+        async move {
+            let _running = running;
+            for report in rebalance_all(&relayer, &relayer.rebalance).await {
+                println!("Rebalanced: {:?}", report);
+            }
+        }
+    });
+    true
+}
+
+// Periodically sweeps and rebalances the paymaster tokens, until the relayer shuts down.
+pub async fn run_rebalancing(relayer: Relayer, interval: Duration) {
+    let mut shutdown = relayer.shutdown.clone();
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {},
+            _ = shutdown.changed() => {},
+        }
+        if *shutdown.borrow() {
+            return;
+        }
+        rebalance(&relayer, &relayer.rebalance).await;
+    }
+}
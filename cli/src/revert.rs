@@ -0,0 +1,598 @@
+use crate::endpoints::EndpointError;
+use alloy::{
+    primitives::{Address, Bytes, B256, U256},
+    sol,
+    sol_types::{Panic, Revert, SolError},
+    transports::{RpcError, TransportErrorKind},
+};
+use std::str::FromStr;
+
+// Custom errors that PaymasterToken inherits from OpenZeppelin (ERC20 and Ownable).
+sol! {
+    error ERC20InsufficientBalance(address sender, uint256 balance, uint256 needed);
+    error ERC20InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+    error ERC20InvalidSender(address sender);
+    error ERC20InvalidReceiver(address receiver);
+    error OwnableUnauthorizedAccount(address account);
+}
+
+// Known reasons why the calls to InteropCenter, CrossPaymaster and PaymasterToken fail.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RevertReason {
+    // InteropCenter
+    #[error("caller is not authorized")]
+    NotAuthorized,
+    #[error("bundle is for another chain")]
+    DestinationChainMismatch,
+    #[error("bundle does not exist")]
+    BundleDoesNotExist,
+    #[error("bundle is empty")]
+    BundleEmpty,
+    #[error("no trusted interop center on the receiving side")]
+    NoTrustedInterop,
+    // InteropCenter doesn't trust the interop center that sent the message.
+    #[error("untrusted source")]
+    UntrustedSource,
+    // Message was not received (forwarded) on the destination chain yet.
+    #[error("message not verified")]
+    MessageNotVerified,
+    #[error("bundle was already executed")]
+    BundleAlreadyExecuted,
+    #[error("message is not a bundle")]
+    WrongBundlePrefix,
+    #[error("wrong chain id")]
+    WrongChainId,
+    #[error("not enough value for the paymaster tokens")]
+    NotEnoughTokens,
+    #[error("remote paymaster token is not set")]
+    RemotePaymasterTokenNotSet,
+    #[error("source chain is not trusted")]
+    SourceChainNotTrusted,
+    #[error("invalid destination chain")]
+    InvalidDestinationChain,
+    #[error("transaction is not of type 113")]
+    WrongTxType,
+    #[error("wrong aliased account in 'from'")]
+    WrongAliasedAccount,
+    #[error("wrong 'to' account")]
+    WrongToAccount,
+    #[error("wrong gas per pubdata")]
+    WrongGasPerPubdata,
+    #[error("max fee and max priority fee differ")]
+    MaxFeeMismatch,
+    #[error("reserved field is set")]
+    ReservedFieldSet,
+    #[error("calldata too short")]
+    DataTooShort,
+    #[error("invalid selector")]
+    InvalidSelector,
+    #[error("bundle hash doesn't match")]
+    BundleHashMismatch,
+    #[error("fee bundle hash doesn't match")]
+    FeesBundleHashMismatch,
+    #[error("factory deps are not supported")]
+    FactoryDepsNotSupported,
+    #[error("interop center has no paymaster set")]
+    NoPaymasterSet,
+    #[error("untrusted interop center")]
+    UntrustedInteropCenter,
+    #[error("interop call failed")]
+    InteropCallFailed,
+    // InteropCenter (as account) or CrossPaymaster.
+    #[error("failed to pay the fee to the operator")]
+    FeePaymentFailed,
+    // CrossPaymaster
+    #[error("remote paymaster not set")]
+    RemotePaymasterNotSet,
+    #[error("paymaster input is not one of the supported paymaster flows")]
+    UnsupportedPaymasterFlow,
+    #[error("approval based paymaster flow is for another token")]
+    WrongPaymasterFlowToken,
+    #[error("approval based paymaster flow allows less than the fee")]
+    PaymasterFlowAllowanceTooLow,
+    // PaymasterToken
+    #[error("sender is not a trusted aliased account")]
+    UntrustedAliasedAccount,
+    #[error("no bridge on destination chain")]
+    NoBridge,
+    #[error("can only be called by interop center")]
+    OnlyInterop,
+    #[error("not enough base token to redeem the tokens")]
+    NotEnoughToRedeem,
+    #[error("failed to send the redeemed base token")]
+    RedeemTransferFailed,
+    #[error("insufficient token balance of {sender}: {balance} < {needed}")]
+    InsufficientBalance {
+        sender: Address,
+        balance: U256,
+        needed: U256,
+    },
+    #[error("insufficient token allowance of {spender}: {allowance} < {needed}")]
+    InsufficientAllowance {
+        spender: Address,
+        allowance: U256,
+        needed: U256,
+    },
+    #[error("invalid token transfer sender or receiver {0}")]
+    InvalidTransferParty(Address),
+    #[error("{0} is not the owner")]
+    NotOwner(Address),
+    // Generic
+    // The RPC request failed (no revert data) - usually goes away on its own.
+    #[error("RPC request failed: {0}")]
+    Rpc(String),
+    // Message with the bundle (or the fee bundle) was not seen by the relayer yet.
+    #[error("bundle message {0} was not seen yet")]
+    MissingBundle(B256),
+    #[error("panic with code {0}")]
+    Panic(U256),
+    #[error("reverted without reason")]
+    Empty,
+    #[error("{0}")]
+    Other(String),
+}
+
+// Revert strings (from the require statements in the contracts) and what they mean.
+const REVERT_STRINGS: &[(&str, RevertReason)] = &[
+    ("Not authorized", RevertReason::NotAuthorized),
+    (
+        "Destination chain mismatch",
+        RevertReason::DestinationChainMismatch,
+    ),
+    ("Bundle does not exist", RevertReason::BundleDoesNotExist),
+    ("Bundle is empty", RevertReason::BundleEmpty),
+    (
+        "No trusted interop on receiving side",
+        RevertReason::NoTrustedInterop,
+    ),
+    ("Untrusted source", RevertReason::UntrustedSource),
+    ("Message not verified", RevertReason::MessageNotVerified),
+    (
+        "This bundle was already executed",
+        RevertReason::BundleAlreadyExecuted,
+    ),
+    (
+        "Wrong prefix - expected bundle prefix",
+        RevertReason::WrongBundlePrefix,
+    ),
+    ("wrong chain id", RevertReason::WrongChainId),
+    (
+        "Not enough tokens - add more value",
+        RevertReason::NotEnoughTokens,
+    ),
+    (
+        "remote paymaster token is not set",
+        RevertReason::RemotePaymasterTokenNotSet,
+    ),
+    (
+        "source chain not trusted",
+        RevertReason::SourceChainNotTrusted,
+    ),
+    (
+        "invalid destination chain",
+        RevertReason::InvalidDestinationChain,
+    ),
+    ("Wrong tx type - expected 113", RevertReason::WrongTxType),
+    (
+        "wrong aliased account in from",
+        RevertReason::WrongAliasedAccount,
+    ),
+    ("wrong to account", RevertReason::WrongToAccount),
+    (
+        "Wrong gas per pubdata constant",
+        RevertReason::WrongGasPerPubdata,
+    ),
+    (
+        "Max fee and max prio should be equal",
+        RevertReason::MaxFeeMismatch,
+    ),
+    (
+        "reserved field must not be set",
+        RevertReason::ReservedFieldSet,
+    ),
+    ("Data too short", RevertReason::DataTooShort),
+    (
+        "Data must be at least 100 bytes",
+        RevertReason::DataTooShort,
+    ),
+    ("invalid selector", RevertReason::InvalidSelector),
+    ("Bundle hash doesnt match", RevertReason::BundleHashMismatch),
+    (
+        "FeesBundleHash doesnt match",
+        RevertReason::FeesBundleHashMismatch,
+    ),
+    (
+        "no factory deps for now",
+        RevertReason::FactoryDepsNotSupported,
+    ),
+    (
+        "InteropCenter has no paymaster set",
+        RevertReason::NoPaymasterSet,
+    ),
+    (
+        "Untrusted interop center",
+        RevertReason::UntrustedInteropCenter,
+    ),
+    ("Interop call failed", RevertReason::InteropCallFailed),
+    (
+        "Failed to pay the fee to the operator",
+        RevertReason::FeePaymentFailed,
+    ),
+    (
+        "remote paymaster not set",
+        RevertReason::RemotePaymasterNotSet,
+    ),
+    (
+        "Unsupported paymaster flow",
+        RevertReason::UnsupportedPaymasterFlow,
+    ),
+    (
+        "Wrong paymaster flow token",
+        RevertReason::WrongPaymasterFlowToken,
+    ),
+    (
+        "Paymaster flow allowance too low",
+        RevertReason::PaymasterFlowAllowanceTooLow,
+    ),
+    (
+        "msg sender is not trusted aliased account",
+        RevertReason::UntrustedAliasedAccount,
+    ),
+    ("No bridge on destination chain", RevertReason::NoBridge),
+    ("Can only be called by interop", RevertReason::OnlyInterop),
+    (
+        "Not enough base token to redeem",
+        RevertReason::NotEnoughToRedeem,
+    ),
+    (
+        "Failed to send base token",
+        RevertReason::RedeemTransferFailed,
+    ),
+];
+
+impl From<EndpointError> for RevertReason {
+    fn from(error: EndpointError) -> Self {
+        RevertReason::Rpc(error.to_string())
+    }
+}
+
+// What to do with a message, whose transaction would revert.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RevertAction {
+    // Error might go away on its own (for example once the message is forwarded).
+    RetryLater,
+    // Nothing left to do.
+    Skip,
+    // Needs an operator.
+    Alert,
+}
+
+impl RevertReason {
+    // Maps the revert string (or the whole error message, that contains it) to the known reasons.
+    pub fn from_message(message: &str) -> Self {
+        REVERT_STRINGS
+            .iter()
+            .find(|(revert_string, _)| message.contains(revert_string))
+            .map(|(_, reason)| reason.clone())
+            .unwrap_or_else(|| RevertReason::Other(message.to_string()))
+    }
+
+    // Decodes the ABI encoded revert data - Error(string), Panic(uint256) or one of the custom errors.
+    pub fn from_revert_data(data: &[u8]) -> Option<Self> {
+        if data.is_empty() {
+            return Some(RevertReason::Empty);
+        }
+        if let Ok(revert) = Revert::abi_decode(data, true) {
+            return Some(RevertReason::from_message(&revert.reason));
+        }
+        if let Ok(panic) = Panic::abi_decode(data, true) {
+            return Some(RevertReason::Panic(panic.code));
+        }
+        if let Ok(error) = ERC20InsufficientBalance::abi_decode(data, true) {
+            return Some(RevertReason::InsufficientBalance {
+                sender: error.sender,
+                balance: error.balance,
+                needed: error.needed,
+            });
+        }
+        if let Ok(error) = ERC20InsufficientAllowance::abi_decode(data, true) {
+            return Some(RevertReason::InsufficientAllowance {
+                spender: error.spender,
+                allowance: error.allowance,
+                needed: error.needed,
+            });
+        }
+        if let Ok(error) = ERC20InvalidSender::abi_decode(data, true) {
+            return Some(RevertReason::InvalidTransferParty(error.sender));
+        }
+        if let Ok(error) = ERC20InvalidReceiver::abi_decode(data, true) {
+            return Some(RevertReason::InvalidTransferParty(error.receiver));
+        }
+        if let Ok(error) = OwnableUnauthorizedAccount::abi_decode(data, true) {
+            return Some(RevertReason::NotOwner(error.account));
+        }
+        None
+    }
+
+    pub fn from_rpc_error(error: &RpcError<TransportErrorKind>) -> Self {
+        // Prefer the revert data from the error, and fall back to the message.
+        if let Some(payload) = error.as_error_resp() {
+            let reason = payload
+                .data
+                .as_ref()
+                .and_then(|data| serde_json::from_str::<String>(data.get()).ok())
+                .and_then(|data| Bytes::from_str(&data).ok())
+                .and_then(|data| RevertReason::from_revert_data(&data));
+            if let Some(reason) = reason {
+                return reason;
+            }
+            return RevertReason::from_message(&payload.message);
+        }
+        // No response from the node (transport error, timeout..) - nothing reverted.
+        RevertReason::Rpc(error.to_string())
+    }
+
+    pub fn from_contract_error(error: &alloy::contract::Error) -> Self {
+        match error {
+            alloy::contract::Error::TransportError(error) => RevertReason::from_rpc_error(error),
+            _ => RevertReason::from_message(&error.to_string()),
+        }
+    }
+
+    // Short, stable name of the reason - for the status output and for grepping the logs.
+    pub fn label(&self) -> &'static str {
+        match self {
+            RevertReason::NotAuthorized => "not_authorized",
+            RevertReason::DestinationChainMismatch => "destination_chain_mismatch",
+            RevertReason::BundleDoesNotExist => "bundle_does_not_exist",
+            RevertReason::BundleEmpty => "bundle_empty",
+            RevertReason::NoTrustedInterop => "no_trusted_interop",
+            RevertReason::UntrustedSource => "untrusted_source",
+            RevertReason::MessageNotVerified => "message_not_verified",
+            RevertReason::BundleAlreadyExecuted => "bundle_already_executed",
+            RevertReason::WrongBundlePrefix => "wrong_bundle_prefix",
+            RevertReason::WrongChainId => "wrong_chain_id",
+            RevertReason::NotEnoughTokens => "not_enough_tokens",
+            RevertReason::RemotePaymasterTokenNotSet => "remote_paymaster_token_not_set",
+            RevertReason::SourceChainNotTrusted => "source_chain_not_trusted",
+            RevertReason::InvalidDestinationChain => "invalid_destination_chain",
+            RevertReason::WrongTxType => "wrong_tx_type",
+            RevertReason::WrongAliasedAccount => "wrong_aliased_account",
+            RevertReason::WrongToAccount => "wrong_to_account",
+            RevertReason::WrongGasPerPubdata => "wrong_gas_per_pubdata",
+            RevertReason::MaxFeeMismatch => "max_fee_mismatch",
+            RevertReason::ReservedFieldSet => "reserved_field_set",
+            RevertReason::DataTooShort => "data_too_short",
+            RevertReason::InvalidSelector => "invalid_selector",
+            RevertReason::BundleHashMismatch => "bundle_hash_mismatch",
+            RevertReason::FeesBundleHashMismatch => "fees_bundle_hash_mismatch",
+            RevertReason::FactoryDepsNotSupported => "factory_deps_not_supported",
+            RevertReason::NoPaymasterSet => "no_paymaster_set",
+            RevertReason::UntrustedInteropCenter => "untrusted_interop_center",
+            RevertReason::InteropCallFailed => "interop_call_failed",
+            RevertReason::FeePaymentFailed => "fee_payment_failed",
+            RevertReason::RemotePaymasterNotSet => "remote_paymaster_not_set",
+            RevertReason::UnsupportedPaymasterFlow => "unsupported_paymaster_flow",
+            RevertReason::WrongPaymasterFlowToken => "wrong_paymaster_flow_token",
+            RevertReason::PaymasterFlowAllowanceTooLow => "paymaster_flow_allowance_too_low",
+            RevertReason::UntrustedAliasedAccount => "untrusted_aliased_account",
+            RevertReason::NoBridge => "no_bridge",
+            RevertReason::OnlyInterop => "only_interop",
+            RevertReason::NotEnoughToRedeem => "not_enough_to_redeem",
+            RevertReason::RedeemTransferFailed => "redeem_transfer_failed",
+            RevertReason::InsufficientBalance { .. } => "insufficient_balance",
+            RevertReason::InsufficientAllowance { .. } => "insufficient_allowance",
+            RevertReason::InvalidTransferParty(_) => "invalid_transfer_party",
+            RevertReason::NotOwner(_) => "not_owner",
+            RevertReason::Rpc(_) => "rpc_error",
+            RevertReason::MissingBundle(_) => "missing_bundle",
+            RevertReason::Panic(_) => "panic",
+            RevertReason::Empty => "empty",
+            RevertReason::Other(_) => "other",
+        }
+    }
+
+    // Label and description, as used in the logs and in the message status.
+    pub fn describe(&self) -> String {
+        format!("[{}] {}", self.label(), self)
+    }
+
+    pub fn action(&self) -> RevertAction {
+        match self {
+            RevertReason::MessageNotVerified
+            | RevertReason::Rpc(_)
+            | RevertReason::MissingBundle(_) => RevertAction::RetryLater,
+            RevertReason::BundleAlreadyExecuted => RevertAction::Skip,
+            _ => RevertAction::Alert,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::address;
+
+    #[test]
+    fn from_message() {
+        for (revert_string, reason) in REVERT_STRINGS {
+            assert_eq!(&RevertReason::from_message(revert_string), reason);
+            // Node errors wrap the revert string.
+            let message = format!("execution reverted: {}", revert_string);
+            assert_eq!(&RevertReason::from_message(&message), reason);
+        }
+        assert_eq!(
+            RevertReason::from_message("out of gas"),
+            RevertReason::Other("out of gas".to_string())
+        );
+    }
+
+    #[test]
+    fn from_revert_data_error_string() {
+        for (revert_string, reason) in REVERT_STRINGS {
+            let data = Revert {
+                reason: revert_string.to_string(),
+            }
+            .abi_encode();
+            assert_eq!(RevertReason::from_revert_data(&data).as_ref(), Some(reason));
+        }
+        let data = Revert {
+            reason: "something else".to_string(),
+        }
+        .abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::Other("something else".to_string()))
+        );
+    }
+
+    #[test]
+    fn from_revert_data_custom_errors() {
+        let account = address!("5f3649BBfCE8f62738c8346588e0F62469087d9e");
+        let data = ERC20InsufficientBalance {
+            sender: account,
+            balance: U256::from(1),
+            needed: U256::from(2),
+        }
+        .abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::InsufficientBalance {
+                sender: account,
+                balance: U256::from(1),
+                needed: U256::from(2),
+            })
+        );
+        let data = ERC20InsufficientAllowance {
+            spender: account,
+            allowance: U256::from(3),
+            needed: U256::from(4),
+        }
+        .abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::InsufficientAllowance {
+                spender: account,
+                allowance: U256::from(3),
+                needed: U256::from(4),
+            })
+        );
+        let data = ERC20InvalidSender { sender: account }.abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::InvalidTransferParty(account))
+        );
+        let data = ERC20InvalidReceiver { receiver: account }.abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::InvalidTransferParty(account))
+        );
+        let data = OwnableUnauthorizedAccount { account }.abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::NotOwner(account))
+        );
+        let data = Panic {
+            code: U256::from(0x11),
+        }
+        .abi_encode();
+        assert_eq!(
+            RevertReason::from_revert_data(&data),
+            Some(RevertReason::Panic(U256::from(0x11)))
+        );
+    }
+
+    #[test]
+    fn from_revert_data_unknown() {
+        assert_eq!(
+            RevertReason::from_revert_data(&[]),
+            Some(RevertReason::Empty)
+        );
+        // Unknown selector, and a known one with the broken payload.
+        assert_eq!(
+            RevertReason::from_revert_data(&[0xde, 0xad, 0xbe, 0xef]),
+            None
+        );
+        let data = Revert {
+            reason: "Not authorized".to_string(),
+        }
+        .abi_encode();
+        assert_eq!(RevertReason::from_revert_data(&data[..40]), None);
+    }
+
+    #[test]
+    fn action() {
+        // Go away on their own.
+        for reason in [
+            RevertReason::MessageNotVerified,
+            RevertReason::Rpc("connection refused".to_string()),
+            RevertReason::MissingBundle(B256::repeat_byte(1)),
+        ] {
+            assert_eq!(
+                reason.action(),
+                RevertAction::RetryLater,
+                "{}",
+                reason.label()
+            );
+        }
+        assert_eq!(
+            RevertReason::BundleAlreadyExecuted.action(),
+            RevertAction::Skip
+        );
+        for reason in [
+            RevertReason::NotAuthorized,
+            RevertReason::DestinationChainMismatch,
+            RevertReason::BundleDoesNotExist,
+            RevertReason::BundleEmpty,
+            RevertReason::NoTrustedInterop,
+            RevertReason::UntrustedSource,
+            RevertReason::WrongBundlePrefix,
+            RevertReason::WrongChainId,
+            RevertReason::NotEnoughTokens,
+            RevertReason::RemotePaymasterTokenNotSet,
+            RevertReason::SourceChainNotTrusted,
+            RevertReason::InvalidDestinationChain,
+            RevertReason::WrongTxType,
+            RevertReason::WrongAliasedAccount,
+            RevertReason::WrongToAccount,
+            RevertReason::WrongGasPerPubdata,
+            RevertReason::MaxFeeMismatch,
+            RevertReason::ReservedFieldSet,
+            RevertReason::DataTooShort,
+            RevertReason::InvalidSelector,
+            RevertReason::BundleHashMismatch,
+            RevertReason::FeesBundleHashMismatch,
+            RevertReason::FactoryDepsNotSupported,
+            RevertReason::NoPaymasterSet,
+            RevertReason::UntrustedInteropCenter,
+            RevertReason::InteropCallFailed,
+            RevertReason::FeePaymentFailed,
+            RevertReason::RemotePaymasterNotSet,
+            RevertReason::UnsupportedPaymasterFlow,
+            RevertReason::WrongPaymasterFlowToken,
+            RevertReason::PaymasterFlowAllowanceTooLow,
+            RevertReason::UntrustedAliasedAccount,
+            RevertReason::NoBridge,
+            RevertReason::OnlyInterop,
+            RevertReason::NotEnoughToRedeem,
+            RevertReason::RedeemTransferFailed,
+            RevertReason::Empty,
+            RevertReason::InsufficientBalance {
+                sender: Address::ZERO,
+                balance: U256::ZERO,
+                needed: U256::from(1),
+            },
+            RevertReason::InsufficientAllowance {
+                spender: Address::ZERO,
+                allowance: U256::ZERO,
+                needed: U256::from(1),
+            },
+            RevertReason::InvalidTransferParty(Address::ZERO),
+            RevertReason::NotOwner(Address::ZERO),
+            RevertReason::Panic(U256::from(0x11)),
+            RevertReason::Other("out of gas".to_string()),
+        ] {
+            assert_eq!(reason.action(), RevertAction::Alert, "{}", reason.label());
+        }
+    }
+}
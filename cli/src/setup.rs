@@ -0,0 +1,445 @@
+use crate::{endpoints::EndpointError, InteropCenter, InteropChain, PaymasterToken};
+use alloy::primitives::{Address, FixedBytes, U256};
+use alloy_zksync::provider::zksync_provider;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeInfo {
+    pub remote_address: Address,
+    pub ratio_nominator: U256,
+    pub ratio_denominator: U256,
+}
+
+impl Display for BridgeInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (ratio {}/{})",
+            self.remote_address, self.ratio_nominator, self.ratio_denominator
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChangeKind {
+    // InteropCenter.trustedSources
+    TrustedSource {
+        current: Address,
+        desired: Address,
+    },
+    // InteropCenter.preferredPaymasters
+    PreferredPaymaster {
+        current: Address,
+        desired: Address,
+    },
+    // PaymasterToken.remoteAddresses and ratios (on the 'token' contract of the destination chain).
+    PaymasterTokenBridge {
+        token: Address,
+        current: BridgeInfo,
+        desired: BridgeInfo,
+    },
+}
+
+impl ChangeKind {
+    fn is_up_to_date(&self) -> bool {
+        match self {
+            ChangeKind::TrustedSource { current, desired }
+            | ChangeKind::PreferredPaymaster { current, desired } => current == desired,
+            ChangeKind::PaymasterTokenBridge {
+                current, desired, ..
+            } => current == desired,
+        }
+    }
+}
+
+// A single difference between the current and desired on-chain state.
+// All the changes are applied on the destination chain, and are about the source chain.
+#[derive(Debug, Clone)]
+pub struct SetupChange {
+    pub source_chain: u64,
+    pub destination_chain: u64,
+    pub kind: ChangeKind,
+}
+
+impl Display for SetupChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ChangeKind::TrustedSource { current, desired } => write!(
+                f,
+                "~ chain {}: trustedSources[{}]\n    - {}\n    + {}",
+                self.destination_chain, self.source_chain, current, desired
+            ),
+            ChangeKind::PreferredPaymaster { current, desired } => write!(
+                f,
+                "~ chain {}: preferredPaymasters[{}]\n    - {}\n    + {}",
+                self.destination_chain, self.source_chain, current, desired
+            ),
+            ChangeKind::PaymasterTokenBridge {
+                token,
+                current,
+                desired,
+            } => write!(
+                f,
+                "~ chain {}: token {} remoteAddresses[{}]\n    - {}\n    + {}",
+                self.destination_chain, token, self.source_chain, current, desired
+            ),
+        }
+    }
+}
+
+pub struct SetupPlan {
+    // Desired state, that the plan was computed for.
+    pub edges: Vec<Edge>,
+    // (source chain, destination chain) pairs, where the destination must not trust the source.
+    pub revoked: Vec<(u64, u64)>,
+    // Or the chain that leaves the network (when computed by compute_removal_plan).
+    pub removed_chain: Option<u64>,
+    pub changes: Vec<SetupChange>,
+    // How many entries already have the desired value.
+    pub unchanged: usize,
+}
+
+impl SetupPlan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Setup plan: {} to change, {} up to date.",
+            self.changes.len(),
+            self.unchanged
+        );
+        for change in &self.changes {
+            println!("  {}", change);
+        }
+    }
+}
+
+pub async fn read_bridge_info(
+    chain: &InteropChain,
+    token: Address,
+    source_chain: u64,
+) -> Result<BridgeInfo, EndpointError> {
+    let source_chain: U256 = source_chain.try_into().unwrap();
+    chain
+        .endpoints
+        .call(|provider| async move {
+            let contract = PaymasterToken::new(token, &provider);
+            Ok::<_, alloy::contract::Error>(BridgeInfo {
+                remote_address: contract.remoteAddresses(source_chain).call().await?._0,
+                ratio_nominator: contract.ratioNominator(source_chain).call().await?._0,
+                ratio_denominator: contract.ratioDenominator(source_chain).call().await?._0,
+            })
+        })
+        .await
+}
+
+// Desired relationship between two chains: destination chain trusts the source chain (its interop center,
+// paymaster and paymaster token), and exchanges paymaster tokens using the given ratio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub source_chain: u64,
+    pub destination_chain: u64,
+    pub ratio_nominator: U256,
+    pub ratio_denominator: U256,
+}
+
+// Full trust between all the chains (including themselves), with ratios based on the base token prices.
+pub fn full_mesh(providers_map: &HashMap<u64, Arc<InteropChain>>) -> Vec<Edge> {
+    let mut edges = vec![];
+    for (_, source_chain) in providers_map {
+        for (_, destination_chain) in providers_map {
+            edges.push(Edge {
+                source_chain: source_chain.chain_id,
+                destination_chain: destination_chain.chain_id,
+                ratio_nominator: U256::from(destination_chain.base_token_price()),
+                ratio_denominator: U256::from(source_chain.base_token_price()),
+            });
+        }
+    }
+    edges
+}
+
+// Compares the current on-chain setup of the chains with the desired one.
+// Revoked pairs can refer to source chains that are not configured (for example the ones that left the network).
+pub async fn compute_plan(
+    providers_map: &HashMap<u64, Arc<InteropChain>>,
+    edges: &[Edge],
+    revoked: &[(u64, u64)],
+) -> SetupPlan {
+    let mut changes = vec![];
+    let mut unchanged = 0;
+
+    let mut paymasters = HashMap::new();
+    let mut tokens = HashMap::new();
+    for (chain_id, chain) in providers_map {
+        paymasters.insert(*chain_id, chain.get_preferred_paymaster().await.unwrap());
+        tokens.insert(*chain_id, chain.get_paymaster_basic_token().await.unwrap());
+    }
+
+    let mut push_change = |source_chain: u64, destination_chain: u64, kind: ChangeKind| {
+        if kind.is_up_to_date() {
+            unchanged += 1;
+        } else {
+            changes.push(SetupChange {
+                source_chain,
+                destination_chain,
+                kind,
+            });
+        }
+    };
+
+    for edge in edges {
+        let source_chain = &providers_map[&edge.source_chain];
+        let destination_chain = &providers_map[&edge.destination_chain];
+        let current = read_current_setup(
+            destination_chain,
+            tokens[&destination_chain.chain_id],
+            source_chain.chain_id,
+        )
+        .await;
+
+        let desired_bridge = BridgeInfo {
+            remote_address: tokens[&source_chain.chain_id],
+            ratio_nominator: edge.ratio_nominator,
+            ratio_denominator: edge.ratio_denominator,
+        };
+        let kinds = [
+            ChangeKind::TrustedSource {
+                current: current.trusted_source,
+                desired: source_chain.interop_address,
+            },
+            ChangeKind::PreferredPaymaster {
+                current: current.preferred_paymaster,
+                desired: paymasters[&source_chain.chain_id],
+            },
+            ChangeKind::PaymasterTokenBridge {
+                token: tokens[&destination_chain.chain_id],
+                current: current.bridge,
+                desired: desired_bridge,
+            },
+        ];
+        for kind in kinds {
+            push_change(source_chain.chain_id, destination_chain.chain_id, kind);
+        }
+    }
+
+    // Revoking only stops the destination chain from accepting messages of the source chain - the preferred
+    // paymaster and the token bridge stay, so the tokens that were already bridged can still be used.
+    for (source_chain, destination_chain) in revoked {
+        let destination = &providers_map[destination_chain];
+        let current =
+            read_current_setup(destination, tokens[destination_chain], *source_chain).await;
+        push_change(
+            *source_chain,
+            *destination_chain,
+            ChangeKind::TrustedSource {
+                current: current.trusted_source,
+                desired: Address::ZERO,
+            },
+        );
+    }
+    changes.sort_by_key(|change| (change.destination_chain, change.source_chain));
+
+    SetupPlan {
+        edges: edges.to_vec(),
+        revoked: revoked.to_vec(),
+        removed_chain: None,
+        changes,
+        unchanged,
+    }
+}
+
+// What the destination chain currently knows about the source chain.
+struct CurrentSetup {
+    trusted_source: Address,
+    preferred_paymaster: Address,
+    bridge: BridgeInfo,
+}
+
+async fn read_current_setup(
+    destination_chain: &InteropChain,
+    destination_token: Address,
+    source_chain: u64,
+) -> CurrentSetup {
+    let source_chain_id: U256 = source_chain.try_into().unwrap();
+    let contract = InteropCenter::new(
+        destination_chain.interop_address,
+        destination_chain.provider(),
+    );
+    CurrentSetup {
+        trusted_source: contract
+            .trustedSources(source_chain_id)
+            .call()
+            .await
+            .unwrap()
+            ._0,
+        preferred_paymaster: contract
+            .preferredPaymasters(source_chain_id)
+            .call()
+            .await
+            .unwrap()
+            ._0,
+        bridge: read_bridge_info(destination_chain, destination_token, source_chain)
+            .await
+            .unwrap(),
+    }
+}
+
+// Plan that clears everything that the chains know about the departing chain
+// (trusted source, preferred paymaster and paymaster token bridge).
+pub async fn compute_removal_plan(
+    providers_map: &HashMap<u64, Arc<InteropChain>>,
+    removed_chain: u64,
+) -> SetupPlan {
+    let revoked: Vec<_> = providers_map
+        .keys()
+        .map(|chain_id| (removed_chain, *chain_id))
+        .collect();
+    let mut plan = compute_plan(providers_map, &[], &revoked).await;
+
+    // Unlike a revoked pair, the departing chain is not coming back - its paymaster and bridge go too.
+    for (chain_id, chain) in providers_map {
+        let token = chain.get_paymaster_basic_token().await.unwrap();
+        let current = read_current_setup(chain, token, removed_chain).await;
+        // Bridge is cleared when its remote address is - ratios don't matter then.
+        let desired_bridge = if current.bridge.remote_address.is_zero() {
+            current.bridge.clone()
+        } else {
+            BridgeInfo {
+                remote_address: Address::ZERO,
+                ratio_nominator: U256::ZERO,
+                ratio_denominator: U256::ZERO,
+            }
+        };
+        let kinds = [
+            ChangeKind::PreferredPaymaster {
+                current: current.preferred_paymaster,
+                desired: Address::ZERO,
+            },
+            ChangeKind::PaymasterTokenBridge {
+                token,
+                current: current.bridge,
+                desired: desired_bridge,
+            },
+        ];
+        for kind in kinds {
+            if kind.is_up_to_date() {
+                plan.unchanged += 1;
+            } else {
+                plan.changes.push(SetupChange {
+                    source_chain: removed_chain,
+                    destination_chain: *chain_id,
+                    kind,
+                });
+            }
+        }
+    }
+    plan.changes
+        .sort_by_key(|change| (change.destination_chain, change.source_chain));
+    plan.removed_chain = Some(removed_chain);
+    plan
+}
+
+pub async fn apply_change(
+    chain: &InteropChain,
+    change: &SetupChange,
+) -> anyhow::Result<FixedBytes<32>> {
+    let admin_provider = zksync_provider()
+        .with_recommended_fillers()
+        .wallet(chain.admin_wallet.clone())
+        .on_http(chain.rpc().parse()?);
+    let source_chain_id: U256 = change.source_chain.try_into().unwrap();
+
+    let tx_hash = match &change.kind {
+        ChangeKind::TrustedSource { desired, .. } => {
+            InteropCenter::new(chain.interop_address, &admin_provider)
+                .addTrustedSource(source_chain_id, *desired)
+                .send()
+                .await?
+                .watch()
+                .await?
+        }
+        ChangeKind::PreferredPaymaster { desired, .. } => {
+            InteropCenter::new(chain.interop_address, &admin_provider)
+                .setPreferredPaymaster(source_chain_id, *desired)
+                .send()
+                .await?
+                .watch()
+                .await?
+        }
+        ChangeKind::PaymasterTokenBridge { token, desired, .. }
+            if desired.remote_address.is_zero() =>
+        {
+            PaymasterToken::new(*token, &admin_provider)
+                .removeOtherBridge(source_chain_id)
+                .send()
+                .await?
+                .watch()
+                .await?
+        }
+        ChangeKind::PaymasterTokenBridge { token, desired, .. } => {
+            PaymasterToken::new(*token, &admin_provider)
+                .addOtherBridge(
+                    source_chain_id,
+                    desired.remote_address,
+                    desired.ratio_nominator,
+                    desired.ratio_denominator,
+                )
+                .send()
+                .await?
+                .watch()
+                .await?
+        }
+    };
+    Ok(tx_hash)
+}
+
+// Applies the plan - chains are updated concurrently, but transactions on a single chain
+// are sent one by one (they share the admin wallet nonce).
+// Returns true if the on-chain state matches the desired one afterwards.
+pub async fn apply_plan(providers_map: &HashMap<u64, Arc<InteropChain>>, plan: &SetupPlan) -> bool {
+    let mut per_chain: BTreeMap<u64, Vec<&SetupChange>> = BTreeMap::new();
+    for change in &plan.changes {
+        per_chain
+            .entry(change.destination_chain)
+            .or_default()
+            .push(change);
+    }
+
+    futures::future::join_all(per_chain.into_iter().map(|(chain_id, changes)| async move {
+        let chain = &providers_map[&chain_id];
+        for change in changes {
+            match apply_change(chain, change).await {
+                Ok(tx_hash) => println!(
+                    "Applied on chain {} (source {}) tx {:?}",
+                    chain_id, change.source_chain, tx_hash
+                ),
+                Err(error) => {
+                    // The rest of the chain's changes are left for the next run (verification reports them).
+                    println!(
+                        "!! Applying on chain {} (source {}) failed: {}",
+                        chain_id, change.source_chain, error
+                    );
+                    break;
+                }
+            }
+        }
+    }))
+    .await;
+
+    let remaining = match plan.removed_chain {
+        Some(removed_chain) => compute_removal_plan(providers_map, removed_chain).await,
+        None => compute_plan(providers_map, &plan.edges, &plan.revoked).await,
+    };
+    if !remaining.is_empty() {
+        println!("!! Setup verification failed - state still differs after applying:");
+        remaining.print();
+    }
+    remaining.is_empty()
+}
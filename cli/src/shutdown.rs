@@ -0,0 +1,50 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+// Work that was started, but didn't reach the receipt yet (forwards, type C submissions).
+#[derive(Default)]
+pub struct InFlight {
+    next_id: AtomicU64,
+    entries: Mutex<BTreeMap<u64, String>>,
+}
+
+// Removes the entry from InFlight when dropped.
+pub struct InFlightGuard {
+    in_flight: Arc<InFlight>,
+    id: u64,
+}
+
+impl InFlight {
+    pub fn start(self: &Arc<Self>, description: String) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.entries.lock().unwrap().insert(id, description);
+        InFlightGuard {
+            in_flight: self.clone(),
+            id,
+        }
+    }
+
+    pub fn pending(&self) -> Vec<String> {
+        self.entries.lock().unwrap().values().cloned().collect()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.entries.lock().unwrap().remove(&self.id);
+    }
+}
+
+pub async fn wait_for_shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).unwrap();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => println!("Got SIGINT"),
+        _ = sigterm.recv() => println!("Got SIGTERM"),
+    }
+}
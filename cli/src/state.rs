@@ -0,0 +1,33 @@
+use crate::{accounts::AliasedAccount, treasury::DailySpend};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+// State that is persisted between relayer restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RelayerState {
+    // Last fully processed block, per chain id.
+    pub checkpoints: BTreeMap<u64, u64>,
+    // Aliased accounts that the relayer came across (listed by 'accounts list').
+    #[serde(default)]
+    pub aliased_accounts: Vec<AliasedAccount>,
+    // Paymaster top-ups sent today, per chain id - so that a restart doesn't reset the daily cap.
+    #[serde(default)]
+    pub paymaster_spend: BTreeMap<u64, DailySpend>,
+}
+
+impl RelayerState {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        // Write to a temporary file first, so that we never leave a half-written state behind.
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    setup::{self, read_bridge_info, Edge},
+    InteropCenter, InteropChain,
+};
+use alloy::primitives::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+// Describes which contracts live on which chain, and how they are wired together.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopologyManifest {
+    pub chains: Vec<ChainManifest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChainManifest {
+    pub chain_id: u64,
+    pub interop_center: Address,
+    pub paymaster: Address,
+    pub paymaster_token: Address,
+    // Setup of this chain towards other (source) chains, by their chain id.
+    pub peers: BTreeMap<u64, PeerManifest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeerManifest {
+    pub trusted_source: Address,
+    pub preferred_paymaster: Address,
+    pub remote_token: Address,
+    pub ratio_nominator: U256,
+    pub ratio_denominator: U256,
+}
+
+pub async fn read_manifest(providers_map: &HashMap<u64, Arc<InteropChain>>) -> TopologyManifest {
+    let mut chains = vec![];
+    for (chain_id, chain) in providers_map {
+        let paymaster_token = chain.get_paymaster_basic_token().await.unwrap();
+        let contract = InteropCenter::new(chain.interop_address, chain.provider());
+
+        let mut peers = BTreeMap::new();
+        for peer_chain_id in providers_map.keys() {
+            let peer: U256 = (*peer_chain_id).try_into().unwrap();
+            let bridge = read_bridge_info(chain, paymaster_token, *peer_chain_id)
+                .await
+                .unwrap();
+            let peer_manifest = PeerManifest {
+                trusted_source: contract.trustedSources(peer).call().await.unwrap()._0,
+                preferred_paymaster: contract.preferredPaymasters(peer).call().await.unwrap()._0,
+                remote_token: bridge.remote_address,
+                ratio_nominator: bridge.ratio_nominator,
+                ratio_denominator: bridge.ratio_denominator,
+            };
+            let is_set = !peer_manifest.trusted_source.is_zero()
+                || !peer_manifest.preferred_paymaster.is_zero()
+                || !peer_manifest.remote_token.is_zero();
+            if is_set {
+                peers.insert(*peer_chain_id, peer_manifest);
+            }
+        }
+
+        chains.push(ChainManifest {
+            chain_id: *chain_id,
+            interop_center: chain.interop_address,
+            paymaster: chain.get_preferred_paymaster().await.unwrap(),
+            paymaster_token,
+            peers,
+        });
+    }
+    chains.sort_by_key(|chain| chain.chain_id);
+    TopologyManifest { chains }
+}
+
+pub async fn export(
+    providers_map: &HashMap<u64, Arc<InteropChain>>,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let manifest = serde_json::to_string_pretty(&read_manifest(providers_map).await)?;
+    match output {
+        Some(path) => {
+            std::fs::write(path, manifest)?;
+            println!("Topology written to {}", path.display());
+        }
+        None => println!("{}", manifest),
+    }
+    Ok(())
+}
+
+// Wires the configured chains in the same way as described in the manifest.
+// Chains are matched by chain id - contracts on them don't have to be at the same addresses.
+pub async fn import(
+    providers_map: &HashMap<u64, Arc<InteropChain>>,
+    manifest_path: &Path,
+    apply: bool,
+) -> anyhow::Result<()> {
+    let manifest: TopologyManifest =
+        serde_json::from_str(&std::fs::read_to_string(manifest_path)?)?;
+
+    let mut edges = vec![];
+    for chain_manifest in &manifest.chains {
+        let Some(chain) = providers_map.get(&chain_manifest.chain_id) else {
+            println!(
+                "!! Chain {} from the manifest is not configured - skipping",
+                chain_manifest.chain_id
+            );
+            continue;
+        };
+        if chain.interop_address != chain_manifest.interop_center {
+            println!(
+                "  Chain {}: interop center is at {} (manifest has {})",
+                chain.chain_id, chain.interop_address, chain_manifest.interop_center
+            );
+        }
+
+        for (peer_chain_id, peer) in &chain_manifest.peers {
+            if !providers_map.contains_key(peer_chain_id) {
+                println!(
+                    "!! Chain {} trusts chain {}, which is not configured - skipping",
+                    chain_manifest.chain_id, peer_chain_id
+                );
+                continue;
+            }
+            edges.push(Edge {
+                source_chain: *peer_chain_id,
+                destination_chain: chain_manifest.chain_id,
+                ratio_nominator: peer.ratio_nominator,
+                ratio_denominator: peer.ratio_denominator,
+            });
+        }
+    }
+
+    let plan = setup::compute_plan(providers_map, &edges, &[]).await;
+    plan.print();
+    if plan.is_empty() {
+        println!("Chains already match the manifest.");
+    } else if apply {
+        anyhow::ensure!(
+            setup::apply_plan(providers_map, &plan).await,
+            "Chains don't match the manifest after applying the plan"
+        );
+    } else {
+        println!("Run with --apply to apply the plan above.");
+    }
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use crate::{to_human_size, InteropChain};
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, B256, U256},
+    providers::Provider,
+};
+use alloy_zksync::{network::transaction_request::TransactionRequest, provider::zksync_provider};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{watch, Mutex};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Amounts are in cents - converted to base tokens with the base token price of each chain.
+#[derive(Debug, Clone)]
+pub struct TreasuryConfig {
+    // Paymaster is topped up once its balance drops below this..
+    pub low_watermark_cents: u64,
+    // ..back up to this.
+    pub high_watermark_cents: u64,
+    // Maximum that is sent to the paymaster of a single chain per (UTC) day.
+    pub daily_cap_cents: u64,
+    // Alert when the funding (admin) wallet has less than this.
+    pub funding_alert_cents: u64,
+    pub interval: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DailySpend {
+    pub day: u64,
+    pub spent: U256,
+}
+
+impl DailySpend {
+    // Starts from zero on a new (UTC) day.
+    pub fn roll_over(&mut self) {
+        if self.day != today() {
+            *self = DailySpend {
+                day: today(),
+                spent: U256::ZERO,
+            };
+        }
+    }
+}
+
+// Result of a single treasury check of a chain.
+#[derive(Debug, Clone, Serialize)]
+pub struct TreasuryReport {
+    pub chain_id: u64,
+    pub paymaster: Address,
+    pub balance: U256,
+    pub topped_up: U256,
+    pub spent_today: U256,
+    pub funding_balance: U256,
+}
+
+// Keeps the paymasters funded from the admin wallet - in the background, outside of the message processing.
+pub struct Treasury {
+    pub config: TreasuryConfig,
+    spend: Mutex<HashMap<u64, DailySpend>>,
+    // Checks of a single chain run one at a time (the interval task and the admin endpoint),
+    // so that the paymaster is not topped up twice for the same low balance.
+    checks: Mutex<HashMap<u64, Arc<Mutex<()>>>>,
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        / SECONDS_PER_DAY
+}
+
+impl Treasury {
+    // Starts with the top-ups that were sent before the restart (they still count towards the daily cap).
+    pub fn new(config: TreasuryConfig, spend: BTreeMap<u64, DailySpend>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            config.low_watermark_cents <= config.high_watermark_cents,
+            "Paymaster low watermark ({} cents) must not be above the high watermark ({} cents)",
+            config.low_watermark_cents,
+            config.high_watermark_cents
+        );
+        Ok(Treasury {
+            config,
+            spend: Mutex::new(spend.into_iter().collect()),
+            checks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Top-ups sent per chain - persisted with the relayer state.
+    pub async fn daily_spend(&self) -> BTreeMap<u64, DailySpend> {
+        self.spend
+            .lock()
+            .await
+            .iter()
+            .map(|(chain_id, spend)| (*chain_id, *spend))
+            .collect()
+    }
+
+    // Checks the chain every interval, until it is removed or the relayer shuts down.
+    pub async fn run(
+        self: Arc<Self>,
+        chain: Arc<InteropChain>,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        loop {
+            if *shutdown.borrow() || chain.removed.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Err(error) = self.check(&chain).await {
+                println!(
+                    "!! Treasury check on chain {} failed: {}",
+                    chain.chain_id, error
+                );
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.config.interval) => {},
+                _ = shutdown.changed() => {},
+            }
+        }
+    }
+
+    // Tops up the preferred paymaster of the chain, if it is below the low watermark.
+    pub async fn check(&self, chain: &InteropChain) -> anyhow::Result<TreasuryReport> {
+        let chain_lock = self
+            .checks
+            .lock()
+            .await
+            .entry(chain.chain_id)
+            .or_default()
+            .clone();
+        let _chain_lock = chain_lock.lock().await;
+
+        let paymaster = chain.get_preferred_paymaster().await?;
+        let balance = chain.get_balance(paymaster).await?;
+        let mut funding_balance = chain.get_balance(chain.admin_address).await?;
+        let low_watermark = chain.cents_to_tokens(self.config.low_watermark_cents);
+        let high_watermark = chain.cents_to_tokens(self.config.high_watermark_cents);
+        let daily_cap = chain.cents_to_tokens(self.config.daily_cap_cents);
+
+        // The amount is reserved under the spend lock, and sent without it - so that a slow chain
+        // doesn't hold up the checks of the other chains.
+        let amount = {
+            let mut spend = self.spend.lock().await;
+            let spend = spend.entry(chain.chain_id).or_default();
+            spend.roll_over();
+            if balance >= low_watermark {
+                U256::ZERO
+            } else {
+                let wanted = high_watermark - balance;
+                let amount = wanted.min(daily_cap.saturating_sub(spend.spent));
+                if amount < wanted {
+                    println!(
+                        "!! ALERT: daily paymaster top-up cap reached on chain {} (spent {}) - sending {} instead of {}",
+                        chain.chain_id,
+                        to_human_size(spend.spent),
+                        to_human_size(amount),
+                        to_human_size(wanted)
+                    );
+                }
+                if amount > funding_balance {
+                    println!(
+                        "!! ALERT: funding wallet {} on chain {} can't top up the paymaster (has {}, needs {})",
+                        chain.admin_address,
+                        chain.chain_id,
+                        to_human_size(funding_balance),
+                        to_human_size(amount)
+                    );
+                    U256::ZERO
+                } else {
+                    spend.spent += amount;
+                    amount
+                }
+            }
+        };
+
+        let mut topped_up = U256::ZERO;
+        if !amount.is_zero() {
+            match self.top_up(chain, paymaster, amount).await {
+                Ok(tx_hash) => {
+                    println!(
+                        "  Topped up paymaster {} on chain {} with {} tokens: {:?}",
+                        paymaster,
+                        chain.chain_id,
+                        to_human_size(amount),
+                        tx_hash
+                    );
+                    funding_balance -= amount;
+                    topped_up = amount;
+                }
+                Err(error) => {
+                    // Nothing was spent - give the reservation back.
+                    self.release(chain.chain_id, amount).await;
+                    return Err(error);
+                }
+            }
+        }
+
+        if funding_balance < chain.cents_to_tokens(self.config.funding_alert_cents) {
+            println!(
+                "!! ALERT: funding wallet {} on chain {} is running low: {}",
+                chain.admin_address,
+                chain.chain_id,
+                to_human_size(funding_balance)
+            );
+        }
+
+        Ok(TreasuryReport {
+            chain_id: chain.chain_id,
+            paymaster,
+            balance: balance + topped_up,
+            topped_up,
+            spent_today: self.spend.lock().await[&chain.chain_id].spent,
+            funding_balance,
+        })
+    }
+
+    async fn top_up(
+        &self,
+        chain: &InteropChain,
+        paymaster: Address,
+        amount: U256,
+    ) -> anyhow::Result<B256> {
+        let admin_provider = zksync_provider()
+            .with_recommended_fillers()
+            .wallet(chain.admin_wallet.clone())
+            .on_http(chain.rpc().parse()?);
+        let tx = TransactionRequest::default()
+            .with_to(paymaster)
+            .with_value(amount);
+        Ok(admin_provider.send_transaction(tx).await?.watch().await?)
+    }
+
+    async fn release(&self, chain_id: u64, amount: U256) {
+        if let Some(spend) = self.spend.lock().await.get_mut(&chain_id) {
+            spend.spent = spend.spent.saturating_sub(amount);
+        }
+    }
+}
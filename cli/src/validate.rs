@@ -0,0 +1,205 @@
+use crate::{CrossPaymaster, InteropCenter, InteropChain};
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+    sol,
+};
+use std::{collections::HashMap, sync::Arc};
+
+sol! {
+    #[sol(rpc)]
+    interface INamed {
+        function name() external view returns (string memory);
+    }
+}
+
+const INTEROP_CENTER_NAME: &str = "InteropCenter";
+const PAYMASTER_NAME: &str = "SlingshotPaymaster";
+const PAYMASTER_TOKEN_NAME: &str = "SlingshotToken";
+
+struct Check {
+    description: String,
+    result: Result<(), String>,
+}
+
+// Result of the startup checks of the contracts on a single chain.
+pub struct ChainReport {
+    pub chain_id: u64,
+    checks: Vec<Check>,
+}
+
+impl ChainReport {
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|check| check.result.is_ok())
+    }
+
+    pub fn print(&self) {
+        println!("Chain {}:", self.chain_id);
+        for check in &self.checks {
+            match &check.result {
+                Ok(()) => println!("  [ok]   {}", check.description),
+                Err(error) => println!("  [FAIL] {} - {}", check.description, error),
+            }
+        }
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter_map(|check| {
+                check
+                    .result
+                    .as_ref()
+                    .err()
+                    .map(|error| format!("{}: {}", check.description, error))
+            })
+            .collect()
+    }
+
+    // Returns false if the check failed (so that the checks depending on it can be skipped).
+    fn add(&mut self, description: String, result: Result<(), String>) -> bool {
+        let ok = result.is_ok();
+        self.checks.push(Check {
+            description,
+            result,
+        });
+        ok
+    }
+}
+
+async fn has_code(chain: &InteropChain, address: Address) -> Result<(), String> {
+    if address.is_zero() {
+        return Err("address is not set".to_string());
+    }
+    let code = chain
+        .endpoints
+        .call(|provider| async move { provider.get_code_at(address).await })
+        .await
+        .map_err(|error| error.to_string())?;
+    if code.is_empty() {
+        return Err(format!("no code at {}", address));
+    }
+    Ok(())
+}
+
+async fn has_name(chain: &InteropChain, address: Address, expected: &str) -> Result<(), String> {
+    let name = chain
+        .endpoints
+        .call(|provider| async move {
+            INamed::new(address, &provider)
+                .name()
+                .call()
+                .await
+                .map(|result| result._0)
+        })
+        .await
+        .map_err(|error| error.to_string())?;
+    if name != expected {
+        return Err(format!("name() returned '{}'", name));
+    }
+    Ok(())
+}
+
+// Checks that the configured address is really an interop center, and that its paymaster and
+// paymaster token are in place.
+pub async fn validate_chain(chain: &InteropChain) -> ChainReport {
+    let mut report = ChainReport {
+        chain_id: chain.chain_id,
+        checks: vec![],
+    };
+
+    let interop_address = chain.interop_address;
+    if !report.add(
+        format!("interop center {} has code", interop_address),
+        has_code(chain, interop_address).await,
+    ) || !report.add(
+        format!("interop center name() is '{}'", INTEROP_CENTER_NAME),
+        has_name(chain, interop_address, INTEROP_CENTER_NAME).await,
+    ) {
+        return report;
+    }
+
+    let chain_id: U256 = chain.chain_id.try_into().unwrap();
+    let paymaster = chain
+        .endpoints
+        .call(|provider| async move {
+            InteropCenter::new(interop_address, &provider)
+                .preferredPaymasters(chain_id)
+                .call()
+                .await
+                .map(|result| result._0)
+        })
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|paymaster| {
+            if paymaster.is_zero() {
+                Err("not set".to_string())
+            } else {
+                Ok(paymaster)
+            }
+        });
+    if !report.add(
+        format!("preferredPaymasters({}) is set", chain.chain_id),
+        paymaster.clone().map(|_| ()),
+    ) {
+        return report;
+    }
+    let paymaster = paymaster.unwrap();
+    if !report.add(
+        format!("paymaster {} has code", paymaster),
+        has_code(chain, paymaster).await,
+    ) || !report.add(
+        format!("paymaster name() is '{}'", PAYMASTER_NAME),
+        has_name(chain, paymaster, PAYMASTER_NAME).await,
+    ) {
+        return report;
+    }
+
+    let token = chain
+        .endpoints
+        .call(|provider| async move {
+            CrossPaymaster::new(paymaster, &provider)
+                .paymasterTokenAddress()
+                .call()
+                .await
+                .map(|result| result.paymasterTokenAddress)
+        })
+        .await
+        .map_err(|error| error.to_string());
+    let token_check = match &token {
+        Ok(token) => has_code(chain, *token).await,
+        Err(error) => Err(error.clone()),
+    };
+    if report.add("paymaster token has code".to_string(), token_check) {
+        report.add(
+            format!("paymaster token name() is '{}'", PAYMASTER_TOKEN_NAME),
+            has_name(chain, token.unwrap(), PAYMASTER_TOKEN_NAME).await,
+        );
+    }
+    report
+}
+
+// Validates all the chains, prints the report and fails if any of the checks didn't pass.
+pub async fn validate(providers_map: &HashMap<u64, Arc<InteropChain>>) -> anyhow::Result<()> {
+    let mut reports = vec![];
+    for chain in providers_map.values() {
+        reports.push(validate_chain(chain).await);
+    }
+    reports.sort_by_key(|report| report.chain_id);
+
+    println!("Contract validation:");
+    for report in &reports {
+        report.print();
+    }
+    let failed: Vec<_> = reports
+        .iter()
+        .filter(|report| !report.is_ok())
+        .map(|report| report.chain_id)
+        .collect();
+    anyhow::ensure!(
+        failed.is_empty(),
+        "Contract validation failed on chains {:?} - check the interop addresses",
+        failed
+    );
+    Ok(())
+}
@@ -6,11 +6,12 @@ import "../lib/forge-std/src/console2.sol";
 import "../lib/openzeppelin-contracts/contracts/token/ERC20/ERC20.sol";
 import "../lib/openzeppelin-contracts/contracts/access/Ownable.sol";
 import {IPaymaster, ExecutionResult, PAYMASTER_VALIDATION_SUCCESS_MAGIC} from "../lib/era-contracts/system-contracts/contracts/interfaces/IPaymaster.sol";
+import {IPaymasterFlow} from "../lib/era-contracts/system-contracts/contracts/interfaces/IPaymasterFlow.sol";
 import {Transaction, TransactionHelper} from "../lib/era-contracts/system-contracts/contracts/libraries/TransactionHelper.sol";
 import {PaymasterToken} from "../src/PaymasterToken.sol";
 import {InteropCenter} from "../src/InteropCenter.sol";
 
-contract CrossPaymaster is IPaymaster {
+contract CrossPaymaster is IPaymaster, Ownable {
     using TransactionHelper for *;
 
     address public paymasterTokenAddress;
@@ -19,7 +20,7 @@ contract CrossPaymaster is IPaymaster {
     constructor(
         address _paymasterTokenAddress,
         address _interopCenterAddress
-    ) payable {
+    ) payable Ownable(msg.sender) {
         paymasterTokenAddress = _paymasterTokenAddress;
         interopCenterAddress = _interopCenterAddress;
     }
@@ -28,6 +29,45 @@ contract CrossPaymaster is IPaymaster {
         return "SlingshotPaymaster";
     }
 
+    // Moves the collected paymaster tokens out - so that they can be redeemed
+    // or sent to the chains whose paymasters are running low.
+    function withdrawTokens(address to, uint256 amount) external onlyOwner {
+        PaymasterToken(paymasterTokenAddress).transfer(to, amount);
+    }
+
+    // Checks the input that the user asked for (destinationPaymasterInput) - it is either empty,
+    // or one of the standard paymaster flows. Tokens are always taken with transferFrom.
+    function checkPaymasterFlow(
+        bytes memory flowInput,
+        uint256 tokensToPay
+    ) public view {
+        if (flowInput.length == 0) {
+            return;
+        }
+        require(flowInput.length >= 4, "Unsupported paymaster flow");
+        bytes4 selector = bytes4(flowInput);
+        if (selector == IPaymasterFlow.general.selector) {
+            return;
+        }
+        require(
+            selector == IPaymasterFlow.approvalBased.selector,
+            "Unsupported paymaster flow"
+        );
+        bytes memory data = new bytes(flowInput.length - 4);
+        for (uint256 i = 0; i < data.length; i++) {
+            data[i] = flowInput[i + 4];
+        }
+        (address token, uint256 minAllowance, ) = abi.decode(
+            data,
+            (address, uint256, bytes)
+        );
+        require(token == paymasterTokenAddress, "Wrong paymaster flow token");
+        require(
+            minAllowance >= tokensToPay,
+            "Paymaster flow allowance too low"
+        );
+    }
+
     function validateAndPayForPaymasterTransaction(
         bytes32, // _txHash,
         bytes32, // _suggestedSignedHash,
@@ -45,34 +85,18 @@ contract CrossPaymaster is IPaymaster {
         // -- and then pay for the user.
 
         // Anyone could try to call this paymaster, to let's see that the transaction is really legit.
-        InteropCenter(interopCenterAddress).verifyPotentialTransaction(
-            _transaction
-        );
-        InteropCenter.InteropMessage memory message = InteropCenter(
-            interopCenterAddress
-        ).transactionToInteropMessage(_transaction);
-
-        bytes32 msgHash = keccak256(abi.encode(message));
-        console2.log("Computed msg hash");
-        console2.logBytes32(msgHash);
-
-        bytes memory transactionInteropProof = new bytes(0);
-
-        require(
-            InteropCenter(interopCenterAddress).verifyInteropMessage(
-                msgHash,
-                transactionInteropProof
-            ),
-            "interop message missing"
-        );
+        InteropCenter(interopCenterAddress).verifyAuthorization(_transaction);
 
         console2.log("message is legit - unpacking fee");
-        InteropCenter.InteropMessage memory feeMessage = abi.decode(
-            _transaction.paymasterInput,
-            (InteropCenter.InteropMessage)
-        );
+        (
+            InteropCenter.InteropMessage memory feeMessage,
+            bytes memory flowInput
+        ) = abi.decode(
+                _transaction.paymasterInput,
+                (InteropCenter.InteropMessage, bytes)
+            );
         console2.log("Fee unpacked");
-        // Todo - proof should be taken from within signature.
+        // Fee bundle is a separate message - no proof for it yet.
         bytes memory proof = new bytes(0);
 
         // executing fee bundle.
@@ -91,6 +115,7 @@ contract CrossPaymaster is IPaymaster {
         console2.log("Current balance", currentBalance);
 
         uint256 tokensToPay = _transaction.maxFeePerGas * _transaction.gasLimit;
+        checkPaymasterFlow(flowInput, tokensToPay);
         console2.log("Charging user ", tokensToPay);
         PaymasterToken(paymasterTokenAddress).transferFrom(
             from,
@@ -238,6 +238,11 @@ contract InteropCenter {
         return _getZKSyncCreate2Address(salt);
     }
 
+    // Bytecode hash of the aliased accounts - so that their addresses can be computed off chain.
+    function aliasedAccountBytecodeHash() public pure returns (bytes32) {
+        return getZKSyncBytecodeHash(type(InteropAccount).creationCode);
+    }
+
     function getRemoteAliasedAccount(
         address sourceAccount,
         uint256 destinationChainId
@@ -722,6 +727,40 @@ contract InteropCenter {
         bytes32 feesBundleHash;
     }
 
+    // Signature of the type C transaction: abi.encode(TransactionReservedStuff, proof) - the proof
+    // is for the interop message that carried the transaction.
+    function decodeSignature(
+        bytes memory signature
+    )
+        public
+        pure
+        returns (TransactionReservedStuff memory stuff, bytes memory proof)
+    {
+        (stuff, proof) = abi.decode(
+            signature,
+            (TransactionReservedStuff, bytes)
+        );
+    }
+
+    // Checks that the transaction really comes from the interop message of the source chain sender,
+    // that its aliased account is in 'from' - and that the message was not used yet.
+    function verifyAuthorization(Transaction memory transaction) public view {
+        verifyPotentialTransaction(transaction);
+
+        (
+            TransactionReservedStuff memory stuff,
+            bytes memory proof
+        ) = decodeSignature(transaction.signature);
+        bytes32 msgHash = keccak256(
+            abi.encode(transactionToInteropMessage(transaction))
+        );
+        require(verifyInteropMessage(msgHash, proof), "Message not verified");
+        require(
+            executedBundles[stuff.bundleHash] == false,
+            "This bundle was already executed"
+        );
+    }
+
     function transactionToInteropMessage(
         Transaction memory transaction
     ) public pure returns (InteropMessage memory) {
@@ -736,9 +775,8 @@ contract InteropCenter {
             abi.encode(interopTx)
         );
 
-        TransactionReservedStuff memory stuff = abi.decode(
-            transaction.signature,
-            (TransactionReservedStuff)
+        (TransactionReservedStuff memory stuff, ) = decodeSignature(
+            transaction.signature
         );
 
         InteropMessage memory message = InteropMessage({
@@ -755,9 +793,8 @@ contract InteropCenter {
     ) public view {
         //console2.log("Starting verification - unpacking from signature");
 
-        TransactionReservedStuff memory stuff = abi.decode(
-            transaction.signature,
-            (TransactionReservedStuff)
+        (TransactionReservedStuff memory stuff, ) = decodeSignature(
+            transaction.signature
         );
         //console2.log("stuff unpacked from sig");
 
@@ -856,10 +893,15 @@ contract InteropCenter {
 
         require(transaction.factoryDeps.length == 0, "no factory deps for now");
 
-        // if feesBundle are set - they should travel in paymaster input.
+        // if feesBundle are set - they should travel in paymaster input,
+        // together with the input for the paymaster (destinationPaymasterInput).
         if (stuff.feesBundleHash != bytes32(0)) {
+            (InteropMessage memory feesBundle, ) = abi.decode(
+                transaction.paymasterInput,
+                (InteropMessage, bytes)
+            );
             require(
-                keccak256(transaction.paymasterInput) == stuff.feesBundleHash,
+                keccak256(abi.encode(feesBundle)) == stuff.feesBundleHash,
                 "FeesBundleHash doesnt match"
             );
         }
@@ -869,9 +911,8 @@ contract InteropCenter {
         Transaction memory transaction
     ) public pure returns (InteropTransaction memory) {
         //console2.log("Starting internal conversion. unpacking stuff..");
-        TransactionReservedStuff memory stuff = abi.decode(
-            transaction.signature,
-            (TransactionReservedStuff)
+        (TransactionReservedStuff memory stuff, ) = decodeSignature(
+            transaction.signature
         );
 
         //console2.log("stuff unpacked");
@@ -881,7 +922,10 @@ contract InteropCenter {
         if (stuff.feesBundleHash == bytes32(0)) {
             paymasterInput = transaction.paymasterInput;
         } else {
-            paymasterInput = "";
+            (, paymasterInput) = abi.decode(
+                transaction.paymasterInput,
+                (InteropMessage, bytes)
+            );
         }
 
         InteropTransaction memory result = InteropTransaction({
@@ -950,35 +994,13 @@ contract InteropAccount is IAccount {
         // If we're using the preferred paymaster - it will take care of all the verification.
         // Otherwise, we have to verify ourselves - and it might fail, as we'll be
         // touching many slots.
-        // FIXME.
+        // FIXME: verifyAuthorization reads InteropCenter storage that is not keyed by this
+        // account (trustedSources, receivedMessages, executedBundles), which the validation
+        // storage rules don't allow - not checked end to end on anvil-zksync yet.
         if (_transaction.paymaster != uint256(uint160(preferredPaymaster))) {
-            //console2.log("Signature len", _transaction.signature.length);
-            // We have to verify following things:
-            //
-            // * change this transaction into 'interop message' - and check.
-            //console2.log("Verify incoming message");
-            InteropCenter(trustedInteropCenter).verifyPotentialTransaction(
+            InteropCenter(trustedInteropCenter).verifyAuthorization(
                 _transaction
             );
-            //console2.log("Verification passed.");
-
-            InteropCenter.InteropMessage memory message = InteropCenter(
-                trustedInteropCenter
-            ).transactionToInteropMessage(_transaction);
-
-            bytes32 msgHash = keccak256(abi.encode(message));
-            //console2.log("Computed msg hash");
-            //console2.logBytes32(msgHash);
-
-            bytes memory proof = new bytes(0);
-
-            require(
-                InteropCenter(trustedInteropCenter).verifyInteropMessage(
-                    msgHash,
-                    proof
-                ),
-                "message not verified"
-            );
         }
 
         magic = ACCOUNT_VALIDATION_SUCCESS_MAGIC;
@@ -1042,7 +1064,10 @@ contract InteropAccount is IAccount {
         bytes32, // _txHash,
         bytes32, // _possibleSignedHash,
         Transaction calldata _transaction
-    ) external payable {}
+    ) external payable {
+        // No allowance is granted here - the relayer accepts approvalBased
+        // flows only for CrossPaymaster, which doesn't need one.
+    }
 
     modifier ignoreNonBootloader() {
         if (msg.sender != BOOTLOADER_FORMAL_ADDRESS) {
@@ -42,6 +42,20 @@ contract PaymasterToken is ERC20, Ownable {
         console2.log("Setting address as trusted", aliasedAddress);
     }
 
+    // Removes the bridge on the other chain (for example when that chain leaves the network).
+    // Its aliased account is no longer allowed to mint tokens here.
+    function removeOtherBridge(uint256 sourceChainId) public onlyOwner {
+        address sourceAddress = remoteAddresses[sourceChainId];
+        if (sourceAddress != address(0)) {
+            address aliasedAddress = InteropCenter(interopAddress)
+                .getAliasedAccount(sourceAddress, sourceChainId);
+            trustedAliasedAccounts[aliasedAddress] = false;
+        }
+        delete remoteAddresses[sourceChainId];
+        delete ratioNominator[sourceChainId];
+        delete ratioDenominator[sourceChainId];
+    }
+
     function receiveTokenFromRemote(
         address destinationAddress,
         uint256 amount
@@ -95,6 +109,34 @@ contract PaymasterToken is ERC20, Ownable {
         );
     }
 
+    // Sends the tokens to the other chain in a bundle of their own.
+    function sendToRemoteInNewBundle(
+        uint256 destinationChainId,
+        address remoteRecipient,
+        uint256 amount
+    ) public returns (bytes32) {
+        uint256 bundleId = InteropCenter(interopAddress).startBundle(
+            destinationChainId
+        );
+        sendToRemote(bundleId, destinationChainId, remoteRecipient, amount);
+        return InteropCenter(interopAddress).finishAndSendBundle(bundleId);
+    }
+
+    // Exchanges the owner's tokens back to the base token 1-1 (the reverse of
+    // buyTokens). Only possible up to the base token that this contract holds.
+    // Tokens received from other chains are minted without any base token
+    // behind them - so holders can't redeem, or they would spend the backing
+    // of the tokens bought here.
+    function redeem(uint256 amount) public onlyOwner {
+        require(
+            address(this).balance >= amount,
+            "Not enough base token to redeem"
+        );
+        _burn(msg.sender, amount);
+        (bool success, ) = payable(msg.sender).call{value: amount}("");
+        require(success, "Failed to send base token");
+    }
+
     function buyTokens() public payable returns (uint256) {
         // exchange wei for tokens in  1-1
         uint256 tokens = msg.value;
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: UNLICENSED
 pragma solidity ^0.8.13;
 
-import {InteropCenter} from "../src/InteropCenter.sol";
+import {InteropCenter, InteropAccount} from "../src/InteropCenter.sol";
 import {PaymasterToken} from "../src/PaymasterToken.sol";
 import {CrossPaymaster} from "../src/CrossPaymaster.sol";
 import {Greeter} from "../src/Greeter.sol";
@@ -9,6 +9,8 @@ import {Greeter} from "../src/Greeter.sol";
 import "../src/Greeter.sol";
 import "../lib/forge-std/src/console2.sol";
 import {Transaction, TransactionHelper} from "../lib/era-contracts/system-contracts/contracts/libraries/TransactionHelper.sol";
+import {IPaymasterFlow} from "../lib/era-contracts/system-contracts/contracts/interfaces/IPaymasterFlow.sol";
+import {BOOTLOADER_FORMAL_ADDRESS} from "../lib/era-contracts/system-contracts/contracts/Constants.sol";
 
 import {Test, console} from "../lib/forge-std/src/Test.sol";
 import {TestExt} from "../lib/forge-zksync-std/src/TestExt.sol";
@@ -79,9 +81,9 @@ contract TransactionConversion is Test, TestExt {
                 executionBundle,
                 proof
             ),
-            signature: abi.encode(stuff),
+            signature: abi.encode(stuff, proof),
             factoryDeps: new bytes32[](0),
-            paymasterInput: abi.encode(feeBundle),
+            paymasterInput: abi.encode(feeBundle, bytes("")),
             reservedDynamic: hex""
         });
 
@@ -246,3 +248,495 @@ contract TokensInterop is Test, TestExt {
         console2.log("Deployed greeter at:", address(greeter));
     }
 }
+
+contract BridgeRemoval is Test, TestExt {
+    InteropCenter public interopCenter;
+    PaymasterToken public paymasterToken;
+
+    function setUp() public {}
+
+    function test_RemoveOtherBridge() public {
+        interopCenter = new InteropCenter();
+        paymasterToken = new PaymasterToken(address(interopCenter));
+
+        uint256 remoteChainId = 501;
+        address remoteToken = address(0x1234);
+        paymasterToken.addOtherBridge(remoteChainId, remoteToken, 2, 3);
+
+        address aliased = interopCenter.getAliasedAccount(
+            remoteToken,
+            remoteChainId
+        );
+        require(paymasterToken.trustedAliasedAccounts(aliased));
+
+        paymasterToken.removeOtherBridge(remoteChainId);
+
+        require(paymasterToken.remoteAddresses(remoteChainId) == address(0));
+        require(paymasterToken.ratioNominator(remoteChainId) == 0);
+        require(paymasterToken.ratioDenominator(remoteChainId) == 0);
+        require(!paymasterToken.trustedAliasedAccounts(aliased));
+
+        // Departed chain can no longer mint tokens here.
+        vm.prank(aliased);
+        vm.expectRevert("msg sender is not trusted aliased account");
+        paymasterToken.receiveTokenFromRemote(address(this), 5);
+    }
+}
+
+contract PaymasterRebalancing is Test, TestExt {
+    InteropCenter public interopCenter;
+    PaymasterToken public paymasterToken;
+    CrossPaymaster public crossPaymaster;
+
+    InteropCenter public interopCenter2;
+    PaymasterToken public paymasterToken2;
+
+    function setUp() public {
+        interopCenter = new InteropCenter();
+        paymasterToken = new PaymasterToken(address(interopCenter));
+        crossPaymaster = new CrossPaymaster(
+            address(paymasterToken),
+            address(interopCenter)
+        );
+
+        interopCenter2 = new InteropCenter();
+        paymasterToken2 = new PaymasterToken(address(interopCenter2));
+    }
+
+    receive() external payable {}
+
+    function test_WithdrawAndRedeem() public {
+        vm.deal(address(this), 1000);
+        paymasterToken.buyTokens{value: 1000}();
+        paymasterToken.transfer(address(crossPaymaster), 300);
+
+        // Only the owner can move the collected tokens.
+        vm.prank(address(0x1234));
+        vm.expectRevert();
+        crossPaymaster.withdrawTokens(address(0x1234), 300);
+
+        crossPaymaster.withdrawTokens(address(this), 300);
+        require(paymasterToken.balanceOf(address(crossPaymaster)) == 0);
+        require(paymasterToken.balanceOf(address(this)) == 1000);
+
+        // Only the owner can redeem - holders of the bridged tokens could
+        // otherwise take the base token paid for the local ones.
+        vm.prank(address(0x1234));
+        vm.expectRevert();
+        paymasterToken.redeem(1);
+
+        uint256 balanceBefore = address(this).balance;
+        paymasterToken.redeem(400);
+        require(address(this).balance == balanceBefore + 400);
+        require(paymasterToken.balanceOf(address(this)) == 600);
+
+        // Tokens are backed only by the base token that was paid for them.
+        paymasterToken.mint(address(this), 1000);
+        vm.expectRevert("Not enough base token to redeem");
+        paymasterToken.redeem(1000);
+    }
+
+    function test_SendToRemoteInNewBundle() public {
+        uint256 chainId = block.chainid;
+        interopCenter2.addTrustedSource(chainId, address(interopCenter));
+        paymasterToken.addOtherBridge(chainId, address(paymasterToken2), 2, 1);
+        paymasterToken2.addOtherBridge(chainId, address(paymasterToken), 1, 2);
+
+        paymasterToken.mint(address(this), 100);
+
+        vm.recordLogs();
+        bytes32 bundleHash = paymasterToken.sendToRemoteInNewBundle(
+            chainId,
+            address(this),
+            10
+        );
+        require(paymasterToken.balanceOf(address(this)) == 90);
+        interopCenter2.receiveInteropMessage(bundleHash);
+
+        Vm.Log[] memory logs = vm.getRecordedLogs();
+        bytes memory eventPayload;
+        for (uint256 i = 0; i < logs.length; i++) {
+            if (
+                logs[i].topics[0] ==
+                keccak256("InteropMessageSent(bytes32,address,bytes)")
+            ) {
+                require(bytes32(logs[i].topics[1]) == bundleHash);
+                eventPayload = abi.decode(logs[i].data, (bytes));
+            }
+        }
+        interopCenter2.executeInteropBundle(
+            abi.decode(eventPayload, (InteropCenter.InteropMessage)),
+            "0x"
+        );
+
+        // Ratio 2/1 applied on the way out.
+        require(paymasterToken2.balanceOf(address(this)) == 20);
+    }
+}
+
+contract PaymasterInputComposition is Test, TestExt {
+    InteropCenter public interopCenter;
+    PaymasterToken public paymasterToken;
+    CrossPaymaster public crossPaymaster;
+
+    function setUp() public {
+        interopCenter = new InteropCenter();
+        paymasterToken = new PaymasterToken(address(interopCenter));
+        crossPaymaster = new CrossPaymaster(
+            address(paymasterToken),
+            address(interopCenter)
+        );
+    }
+
+    function test_UserInputTravelsWithFeeBundle() public {
+        address interopOnSource = address(
+            0x6Fb7817d183F7C84A546770338bf1F5d2111e43a
+        );
+        interopCenter.addTrustedSource(99, interopOnSource);
+        address userSender = address(
+            0x5f3649BBfCE8f62738c8346588e0F62469087d9e
+        );
+
+        InteropCenter.InteropMessage memory executionBundle = InteropCenter
+            .InteropMessage({
+                data: hex"0102",
+                sender: address(0),
+                sourceChainId: 55,
+                messageNum: 44
+            });
+        InteropCenter.InteropMessage memory feeBundle = InteropCenter
+            .InteropMessage({
+                data: hex"010204",
+                sender: address(0),
+                sourceChainId: 55,
+                messageNum: 45
+            });
+        bytes memory flowInput = abi.encodeWithSelector(
+            IPaymasterFlow.approvalBased.selector,
+            address(paymasterToken),
+            1000,
+            hex""
+        );
+
+        InteropCenter.TransactionReservedStuff memory stuff = InteropCenter
+            .TransactionReservedStuff({
+                sourceChainSender: userSender,
+                interopMessageSender: interopOnSource,
+                sourceChainId: 99,
+                messageNum: 0,
+                destinationChainId: block.chainid,
+                bundleHash: keccak256(abi.encode(executionBundle)),
+                feesBundleHash: keccak256(abi.encode(feeBundle))
+            });
+
+        Transaction memory transaction = Transaction({
+            txType: 113,
+            from: uint256(
+                uint160(interopCenter.getAliasedAccount(userSender, 99))
+            ),
+            to: uint256(uint160(address(interopCenter))),
+            gasLimit: 0,
+            gasPerPubdataByteLimit: 50000,
+            maxFeePerGas: 0,
+            maxPriorityFeePerGas: 0,
+            paymaster: uint256(uint160(address(crossPaymaster))),
+            nonce: 0,
+            value: 0,
+            reserved: [uint256(0), uint256(0), uint256(0), uint256(0)],
+            data: abi.encodeWithSignature(
+                "executeInteropBundle((bytes,address,uint256,uint256),bytes)",
+                executionBundle,
+                hex""
+            ),
+            signature: abi.encode(stuff, hex""),
+            factoryDeps: new bytes32[](0),
+            paymasterInput: abi.encode(feeBundle, flowInput),
+            reservedDynamic: hex""
+        });
+
+        interopCenter.verifyPotentialTransaction(transaction);
+        InteropCenter.InteropTransaction memory interopTx = interopCenter
+            .transactionToInteropTransaction(transaction);
+        require(
+            keccak256(interopTx.destinationPaymasterInput) ==
+                keccak256(flowInput)
+        );
+
+        // Fee bundle must still match the hash that the user committed to.
+        feeBundle.messageNum = 46;
+        transaction.paymasterInput = abi.encode(feeBundle, flowInput);
+        vm.expectRevert("FeesBundleHash doesnt match");
+        interopCenter.verifyPotentialTransaction(transaction);
+    }
+
+    function test_PaymasterFlows() public {
+        crossPaymaster.checkPaymasterFlow(hex"", 1000);
+        crossPaymaster.checkPaymasterFlow(
+            abi.encodeWithSelector(IPaymasterFlow.general.selector, hex"01"),
+            1000
+        );
+        crossPaymaster.checkPaymasterFlow(
+            abi.encodeWithSelector(
+                IPaymasterFlow.approvalBased.selector,
+                address(paymasterToken),
+                1000,
+                hex""
+            ),
+            1000
+        );
+
+        vm.expectRevert("Wrong paymaster flow token");
+        crossPaymaster.checkPaymasterFlow(
+            abi.encodeWithSelector(
+                IPaymasterFlow.approvalBased.selector,
+                address(0x1234),
+                1000,
+                hex""
+            ),
+            1000
+        );
+
+        vm.expectRevert("Paymaster flow allowance too low");
+        crossPaymaster.checkPaymasterFlow(
+            abi.encodeWithSelector(
+                IPaymasterFlow.approvalBased.selector,
+                address(paymasterToken),
+                999,
+                hex""
+            ),
+            1000
+        );
+
+        vm.expectRevert("Unsupported paymaster flow");
+        crossPaymaster.checkPaymasterFlow(hex"deadbeef", 1000);
+
+        vm.expectRevert("Unsupported paymaster flow");
+        crossPaymaster.checkPaymasterFlow(hex"01", 1000);
+    }
+}
+
+contract AliasedAccountAuthorization is Test, TestExt {
+    InteropCenter public interopCenter;
+
+    address interopOnSource =
+        address(0x6Fb7817d183F7C84A546770338bf1F5d2111e43a);
+    address userSender = address(0x5f3649BBfCE8f62738c8346588e0F62469087d9e);
+    address attacker = address(0x1234);
+
+    InteropCenter.InteropMessage executionBundle;
+
+    function setUp() public {
+        interopCenter = new InteropCenter();
+        interopCenter.addTrustedSource(99, interopOnSource);
+        executionBundle = InteropCenter.InteropMessage({
+            data: hex"0102",
+            sender: address(0),
+            sourceChainId: 55,
+            messageNum: 44
+        });
+    }
+
+    // Type C transaction, as the relayer builds it (without a paymaster).
+    function buildTransaction(
+        address sourceChainSender,
+        address from
+    ) internal view returns (Transaction memory) {
+        InteropCenter.TransactionReservedStuff memory stuff = InteropCenter
+            .TransactionReservedStuff({
+                sourceChainSender: sourceChainSender,
+                interopMessageSender: interopOnSource,
+                sourceChainId: 99,
+                messageNum: 0,
+                destinationChainId: block.chainid,
+                bundleHash: keccak256(abi.encode(executionBundle)),
+                feesBundleHash: bytes32(0)
+            });
+
+        return
+            Transaction({
+                txType: 113,
+                from: uint256(uint160(from)),
+                to: uint256(uint160(address(interopCenter))),
+                gasLimit: 1000,
+                gasPerPubdataByteLimit: 50000,
+                maxFeePerGas: 10,
+                maxPriorityFeePerGas: 10,
+                paymaster: 0,
+                nonce: 0,
+                value: 0,
+                reserved: [uint256(0), uint256(0), uint256(0), uint256(0)],
+                data: abi.encodeWithSignature(
+                    "executeInteropBundle((bytes,address,uint256,uint256),bytes)",
+                    executionBundle,
+                    hex""
+                ),
+                signature: abi.encode(stuff, hex""),
+                factoryDeps: new bytes32[](0),
+                paymasterInput: hex"",
+                reservedDynamic: hex""
+            });
+    }
+
+    // Marks the interop message of the transaction as received (forwarded by the relayer).
+    function markReceived(Transaction memory transaction) internal {
+        interopCenter.receiveInteropMessage(
+            keccak256(
+                abi.encode(
+                    interopCenter.transactionToInteropMessage(transaction)
+                )
+            )
+        );
+    }
+
+    function test_ReceivedMessageIsAuthorized() public {
+        Transaction memory transaction = buildTransaction(
+            userSender,
+            interopCenter.getAliasedAccount(userSender, 99)
+        );
+        vm.expectRevert("Message not verified");
+        interopCenter.verifyAuthorization(transaction);
+
+        markReceived(transaction);
+        interopCenter.verifyAuthorization(transaction);
+    }
+
+    function test_ForgedSenderOnVictimAccount() public {
+        // The attacker claims to be the user, to spend from the user's aliased account..
+        Transaction memory transaction = buildTransaction(
+            attacker,
+            interopCenter.getAliasedAccount(userSender, 99)
+        );
+        vm.expectRevert("wrong aliased account in from");
+        interopCenter.verifyAuthorization(transaction);
+    }
+
+    function test_ForgedSenderWithoutMessage() public {
+        // ..or rewrites the sender of the user's message - which no longer matches any received message.
+        Transaction memory original = buildTransaction(
+            userSender,
+            interopCenter.getAliasedAccount(userSender, 99)
+        );
+        markReceived(original);
+
+        Transaction memory forged = buildTransaction(
+            attacker,
+            interopCenter.getAliasedAccount(attacker, 99)
+        );
+        vm.expectRevert("Message not verified");
+        interopCenter.verifyAuthorization(forged);
+    }
+
+    function test_UntrustedInteropMessageSender() public {
+        Transaction memory transaction = buildTransaction(
+            userSender,
+            interopCenter.getAliasedAccount(userSender, 99)
+        );
+        (InteropCenter.TransactionReservedStuff memory stuff, ) = interopCenter
+            .decodeSignature(transaction.signature);
+        stuff.interopMessageSender = attacker;
+        transaction.signature = abi.encode(stuff, hex"");
+        vm.expectRevert("Untrusted source");
+        interopCenter.verifyAuthorization(transaction);
+    }
+
+    // Same checks, but driven through the aliased account itself
+    // (as the bootloader would).
+    function deployUserAccount() internal returns (InteropAccount) {
+        interopCenter.setPreferredPaymaster(block.chainid, address(0x5678));
+        return
+            InteropAccount(
+                payable(interopCenter.deployAliasedAccount(userSender, 99))
+            );
+    }
+
+    function test_AccountAcceptsReceivedMessage() public {
+        InteropAccount account = deployUserAccount();
+        Transaction memory transaction = buildTransaction(
+            userSender,
+            address(account)
+        );
+        markReceived(transaction);
+
+        vm.prank(BOOTLOADER_FORMAL_ADDRESS);
+        bytes4 magic = account.validateTransaction(
+            bytes32(0),
+            bytes32(0),
+            transaction
+        );
+        assertEq(magic, account.validateTransaction.selector);
+    }
+
+    function test_AccountRejectsForgedSender() public {
+        InteropAccount account = deployUserAccount();
+        // Even with a received message, the attacker can't pose as the user.
+        Transaction memory transaction = buildTransaction(
+            attacker,
+            address(account)
+        );
+        markReceived(transaction);
+
+        vm.prank(BOOTLOADER_FORMAL_ADDRESS);
+        vm.expectRevert("wrong aliased account in from");
+        account.validateTransaction(bytes32(0), bytes32(0), transaction);
+    }
+}
+
+contract AliasedAccountDerivation is Test, TestExt {
+    // Same vector as the derivation test of the cli (cli/src/accounts.rs).
+    address interopAddress = 0x6Fb7817d183F7C84A546770338bf1F5d2111e43a;
+    address sender = 0x5f3649BBfCE8f62738c8346588e0F62469087d9e;
+    bytes32 bytecodeHash =
+        0x0100000309d6a718960241fb8a688613360d372f228972cd037fbdb39bbf0bde;
+
+    // What the cli computes off chain.
+    function derive(
+        address deployer,
+        bytes32 hash,
+        uint256 sourceChainId,
+        address sourceAccount
+    ) internal pure returns (address) {
+        bytes32 salt = keccak256(
+            abi.encodePacked(sourceChainId, sourceAccount)
+        );
+        return
+            address(
+                uint160(
+                    uint256(
+                        keccak256(
+                            bytes.concat(
+                                keccak256("zksyncCreate2"),
+                                bytes32(uint256(uint160(deployer))),
+                                salt,
+                                hash,
+                                keccak256("")
+                            )
+                        )
+                    )
+                )
+            );
+    }
+
+    function test_PinnedVector() public view {
+        assertEq(
+            derive(interopAddress, bytecodeHash, 500, sender),
+            0xf612B91303ec9f04b1F9c1Db3748f3264Bd935e5
+        );
+        assertEq(
+            derive(interopAddress, bytecodeHash, 501, sender),
+            0x2C7f8C52eAE62Fb3a4cddA31CbAC9811270e81ea
+        );
+    }
+
+    function test_MatchesGetAliasedAccount() public {
+        InteropCenter interopCenter = new InteropCenter();
+        assertEq(
+            interopCenter.getAliasedAccount(sender, 500),
+            derive(
+                address(interopCenter),
+                interopCenter.aliasedAccountBytecodeHash(),
+                500,
+                sender
+            )
+        );
+    }
+}